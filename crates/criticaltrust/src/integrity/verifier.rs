@@ -4,9 +4,10 @@
 use crate::integrity::detect_manifest::{is_package_manifest, FoundPackageManifest};
 use crate::integrity::IntegrityError;
 use crate::manifests::{PackageFile, PackageManifest};
-use crate::sha256::hash_sha256;
+use crate::sha256::{hash_sha256, hash_sha256_reader};
 use crate::signatures::Keychain;
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 
 /// Verify the integrity of a CriticalUp archive or installation.
@@ -68,10 +69,7 @@ impl<'a> IntegrityVerifier<'a> {
     /// the metadata potentially until [`verify`](IntegrityVerifier::verify) is called.
     pub fn add(&mut self, path: &Path, mode: u32, contents: &[u8]) {
         let path_str = path.to_string_lossy().to_string();
-        if !self.loaded_files.insert(path_str.clone()) {
-            self.errors.push(IntegrityError::FileLoadedMultipleTimes {
-                path: path_str.clone(),
-            });
+        if !self.mark_as_loaded(&path_str) {
             return;
         }
 
@@ -80,18 +78,39 @@ impl<'a> IntegrityVerifier<'a> {
                 self.errors.push(err);
             }
         } else {
-            let entry = FoundFile {
-                mode,
-                sha256: hash_sha256(contents),
-            };
+            self.add_found_file(
+                path,
+                &path_str,
+                FoundFile {
+                    mode,
+                    sha256: hash_sha256(contents),
+                },
+            );
+        }
+    }
 
-            if let Some(manifest) = self.referenced_by_manifests_but_missing.remove(path) {
-                self.verify_file(&path_str, &manifest, &entry);
-            } else {
-                self.added_but_not_referenced_by_manifests
-                    .insert(path.into(), entry);
+    /// Include the provided path in the files pending verification, reading its contents from
+    /// `reader` instead of requiring them to be fully loaded in memory.
+    ///
+    /// The reader is always consumed until its end, even when the file would be rejected, which
+    /// allows callers to write the file somewhere else as it's being read. Only package manifests
+    /// are buffered in memory, as they need to be deserialized. Apart from that, this method
+    /// behaves the same as [`add`](IntegrityVerifier::add).
+    pub fn add_reader<R: Read>(&mut self, path: &Path, mode: u32, mut reader: R) -> io::Result<()> {
+        let path_str = path.to_string_lossy().to_string();
+
+        if is_package_manifest(&path_str).is_some() {
+            let mut contents = Vec::new();
+            reader.read_to_end(&mut contents)?;
+            self.add(path, mode, &contents);
+        } else {
+            let sha256 = hash_sha256_reader(reader)?;
+            if self.mark_as_loaded(&path_str) {
+                self.add_found_file(path, &path_str, FoundFile { mode, sha256 });
             }
         }
+
+        Ok(())
     }
 
     /// Perform the final checks and return the outcome of the verification. The method either
@@ -134,6 +153,27 @@ impl<'a> IntegrityVerifier<'a> {
         }
     }
 
+    /// Record the path as loaded, returning `false` (and recording the error) if it was already
+    /// loaded before.
+    fn mark_as_loaded(&mut self, path: &str) -> bool {
+        if self.loaded_files.insert(path.to_string()) {
+            true
+        } else {
+            self.errors
+                .push(IntegrityError::FileLoadedMultipleTimes { path: path.into() });
+            false
+        }
+    }
+
+    fn add_found_file(&mut self, path: &Path, path_str: &str, entry: FoundFile) {
+        if let Some(manifest) = self.referenced_by_manifests_but_missing.remove(path) {
+            self.verify_file(path_str, &manifest, &entry);
+        } else {
+            self.added_but_not_referenced_by_manifests
+                .insert(path.into(), entry);
+        }
+    }
+
     fn add_package_manifest(
         &mut self,
         path: &str,
@@ -166,6 +206,7 @@ impl<'a> IntegrityVerifier<'a> {
         }
 
         let mut proxies_paths = BTreeMap::new();
+        let mut proxies_sha256 = BTreeMap::new();
         let prefix = found.prefix.map(PathBuf::from).unwrap_or_default();
         for file in manifest.files {
             let file_path = prefix.join(&file.path);
@@ -177,6 +218,7 @@ impl<'a> IntegrityVerifier<'a> {
                     .map(|(_dir, name)| name)
                     .unwrap_or(&file_str);
                 proxies_paths.insert(proxy_name.into(), file_path.clone());
+                proxies_sha256.insert(proxy_name.into(), file.sha256.clone());
             }
 
             if let Some(found) = self
@@ -203,6 +245,7 @@ impl<'a> IntegrityVerifier<'a> {
             product: manifest.product,
             package: manifest.package,
             proxies_paths,
+            proxies_sha256,
         });
 
         Ok(())
@@ -233,6 +276,9 @@ pub struct VerifiedPackage {
     pub package: String,
     /// List of the paths of all binaries that need a proxy.
     pub proxies_paths: BTreeMap<String, PathBuf>,
+    /// SHA256 checksums of all binaries that need a proxy, as listed in the signed package
+    /// manifest.
+    pub proxies_sha256: BTreeMap<String, Vec<u8>>,
 }
 
 struct FoundFile {
@@ -297,6 +343,16 @@ mod tests {
             .assert_verified(&[("a", "b")]);
     }
 
+    #[test]
+    fn test_one_manifest_with_files_streamed() {
+        IntegrityTest::new()
+            .streaming()
+            .manifest(ManifestBuilder::new("a", "b").file(&BIN_A).file(&SHARE_A))
+            .file(&BIN_A)
+            .file(&SHARE_A)
+            .assert_verified(&[("a", "b")]);
+    }
+
     #[test]
     fn test_one_manifest_with_files_in_a_prefix() {
         IntegrityTest::new()
@@ -392,6 +448,18 @@ mod tests {
             ]);
     }
 
+    #[test]
+    fn test_files_with_wrong_checksum_streamed() {
+        IntegrityTest::new()
+            .streaming()
+            .manifest(ManifestBuilder::new("a", "b").file(&BIN_A).file(&SHARE_A))
+            .file(&BIN_A.add_content(b"!"))
+            .file(&SHARE_A)
+            .assert_errors(errors![
+                IntegrityError::WrongChecksum { path } if path == "bin/a",
+            ]);
+    }
+
     #[cfg(not(windows))] // Windows does not have file modes
     #[test]
     fn test_files_with_wrong_mode() {
@@ -581,6 +649,18 @@ mod tests {
             ]);
     }
 
+    #[test]
+    fn test_file_loaded_multiple_times_streamed() {
+        IntegrityTest::new()
+            .streaming()
+            .manifest(ManifestBuilder::new("a", "b").file(&BIN_A))
+            .file(&BIN_A)
+            .file(&BIN_A)
+            .assert_errors(errors![
+                IntegrityError::FileLoadedMultipleTimes { path } if path == "bin/a",
+            ]);
+    }
+
     #[test]
     fn test_manifest_loaded_multiple_times() {
         IntegrityTest::new()
@@ -607,6 +687,7 @@ mod tests {
                 product: "a".into(),
                 package: "b".into(),
                 proxies_paths: btreemap! {"a" => "bin/a"},
+                proxies_sha256: btreemap! {"a" => hash_sha256(&BIN_A.contents)},
             }]);
     }
 
@@ -625,6 +706,7 @@ mod tests {
                 product: "a".into(),
                 package: "b".into(),
                 proxies_paths: btreemap! {"a" => "foo/bin/a"},
+                proxies_sha256: btreemap! {"a" => hash_sha256(&BIN_A.contents)},
             }]);
     }
 
@@ -785,6 +867,7 @@ mod tests {
         env: TestEnvironment,
         key: EphemeralKeyPair,
         allow_external_files: bool,
+        streaming: bool,
         files: Vec<TestFile>,
     }
 
@@ -796,6 +879,7 @@ mod tests {
                 env,
                 key,
                 allow_external_files: false,
+                streaming: false,
                 files: Vec::new(),
             }
         }
@@ -805,6 +889,11 @@ mod tests {
             self
         }
 
+        fn streaming(mut self) -> Self {
+            self.streaming = true;
+            self
+        }
+
         fn file(mut self, file: &TestFile) -> Self {
             self.files.push(file.clone());
             self
@@ -900,7 +989,13 @@ mod tests {
                     verifier.allow_external_files(self.allow_external_files);
                     for file in files {
                         let path_str: &str = &file.path;
-                        verifier.add(Path::new(path_str), file.mode, &file.contents);
+                        if self.streaming {
+                            verifier
+                                .add_reader(Path::new(path_str), file.mode, &*file.contents)
+                                .unwrap();
+                        } else {
+                            verifier.add(Path::new(path_str), file.mode, &file.contents);
+                        }
                     }
                     f(verifier.verify());
                 })
@@ -917,6 +1012,7 @@ mod tests {
                 product: self.0.into(),
                 package: self.1.into(),
                 proxies_paths: BTreeMap::new(),
+                proxies_sha256: BTreeMap::new(),
             }
         }
     }
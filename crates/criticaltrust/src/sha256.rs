@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use sha2::{Digest, Sha256};
+use std::io::Read;
 
 /// Helper function to hash bytes with SHA256. It's a simple wrapper on top of the sha2 crate,
 /// turning the three method calls into a function call.
@@ -11,6 +12,14 @@ pub(crate) fn hash_sha256(contents: &[u8]) -> Vec<u8> {
     hasher.finalize().to_vec()
 }
 
+/// Helper function to hash the contents of a reader with SHA256, without loading them all in
+/// memory at once.
+pub(crate) fn hash_sha256_reader<R: Read>(mut reader: R) -> std::io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,4 +37,13 @@ mod tests {
         }
         assert_eq!(HASHED, hashed_hex);
     }
+
+    #[test]
+    fn test_hash_sha256_reader() {
+        let contents = "Hello world".repeat(10_000);
+        assert_eq!(
+            hash_sha256(contents.as_bytes()),
+            hash_sha256_reader(contents.as_bytes()).unwrap()
+        );
+    }
 }
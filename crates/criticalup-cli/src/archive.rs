@@ -0,0 +1,184 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Extraction of package archives, verifying the integrity of each file as it's written to disk.
+
+use crate::errors::Error;
+use criticaltrust::integrity::IntegrityVerifier;
+use criticaltrust::manifests::{ReleaseArtifact, ReleaseArtifactFormat, ReleasePackage};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Artifact formats criticalup is able to unpack, in order of preference. Zstandard archives are
+/// preferred as they are a lot faster to decompress.
+const SUPPORTED_FORMATS: &[ReleaseArtifactFormat] =
+    &[ReleaseArtifactFormat::TarZst, ReleaseArtifactFormat::TarXz];
+
+/// Choose the best artifact of a package among the ones offered by the release manifest.
+pub(crate) fn preferred_artifact(package: &ReleasePackage) -> Result<&ReleaseArtifact, Error> {
+    SUPPORTED_FORMATS
+        .iter()
+        .find_map(|format| package.artifacts.iter().find(|a| a.format == *format))
+        .ok_or_else(|| Error::NoSupportedArtifactFormat {
+            package: package.package.clone(),
+            available: package
+                .artifacts
+                .iter()
+                .map(|a| a.format.to_string())
+                .collect(),
+        })
+}
+
+/// Decompress and unpack a package archive into `dest`, adding every file to the integrity
+/// verifier. Files are added to the verifier with their path relative to `dest`, so that the
+/// verification outcome doesn't depend on where the archive is unpacked.
+///
+/// The archive is processed in a single streaming pass: it's never loaded in memory as a whole,
+/// and the contents of each regular file are hashed while they're written to disk.
+pub(crate) fn unpack_and_verify(
+    archive: impl Read,
+    format: &ReleaseArtifactFormat,
+    dest: &Path,
+    verifier: &mut IntegrityVerifier,
+) -> Result<(), Error> {
+    let decoder: Box<dyn Read> = match format {
+        ReleaseArtifactFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(archive)?),
+        ReleaseArtifactFormat::TarXz => Box::new(xz2::read::XzDecoder::new(archive)),
+        ReleaseArtifactFormat::Unknown => {
+            unreachable!("only supported formats are ever selected")
+        }
+    };
+    let mut archive = tar::Archive::new(decoder);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_unpack_xattrs(true);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let path_on_disk = dest.join(&path);
+
+        // Not all archives include entries for the directories, create them when missing.
+        if let Some(parent) = path_on_disk.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if entry.header().entry_type().is_file() {
+            unpack_file(&mut entry, &path, &path_on_disk, verifier)?;
+        } else {
+            entry.unpack(&path_on_disk)?;
+
+            // Hard links (and symlinks to files) are verified based on the file they point to.
+            if path_on_disk.is_file() {
+                let mode = entry.header().mode()?;
+                verifier.add_reader(&path, mode, File::open(&path_on_disk)?)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a regular file to disk, hashing it with the verifier while it's being written.
+///
+/// This mirrors what `tar::Entry::unpack` does for regular files, which can't be used here as it
+/// doesn't allow inspecting the contents while they're copied.
+fn unpack_file<R: Read>(
+    entry: &mut tar::Entry<R>,
+    path: &Path,
+    path_on_disk: &Path,
+    verifier: &mut IntegrityVerifier,
+) -> Result<(), Error> {
+    let mode = entry.header().mode()?;
+    let mtime = entry.header().mtime()?;
+
+    // Ensure we write a new file rather than writing into an existing one.
+    if path_on_disk.exists() {
+        std::fs::remove_file(path_on_disk)?;
+    }
+    let mut file = File::create(path_on_disk)?;
+
+    verifier.add_reader(
+        path,
+        mode,
+        TeeReader {
+            reader: entry,
+            writer: &mut file,
+        },
+    )?;
+    file.flush()?;
+
+    // Archives with a zero mtime are a problem for some tools, see `tar::Entry::unpack`.
+    let mtime = filetime::FileTime::from_unix_time(mtime.max(1) as i64, 0);
+    filetime::set_file_handle_times(&file, Some(mtime), Some(mtime))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+/// Reader copying everything it reads into a writer.
+struct TeeReader<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..len])?;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(formats: &[ReleaseArtifactFormat]) -> ReleasePackage {
+        ReleasePackage {
+            package: "rustc".into(),
+            artifacts: formats
+                .iter()
+                .map(|format| ReleaseArtifact {
+                    format: format.clone(),
+                    size: 0,
+                    sha256: Vec::new(),
+                })
+                .collect(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_preferred_artifact() {
+        use ReleaseArtifactFormat::*;
+
+        let format = |formats| {
+            preferred_artifact(&package(formats))
+                .unwrap()
+                .format
+                .clone()
+        };
+        assert_eq!(TarZst, format(&[TarXz, TarZst]));
+        assert_eq!(TarZst, format(&[TarZst, TarXz]));
+        assert_eq!(TarZst, format(&[Unknown, TarZst]));
+        assert_eq!(TarXz, format(&[TarXz]));
+        assert_eq!(TarXz, format(&[Unknown, TarXz]));
+
+        assert!(matches!(
+            preferred_artifact(&package(&[Unknown])),
+            Err(Error::NoSupportedArtifactFormat { package, available })
+                if package == "rustc" && available == ["unknown"]
+        ));
+        assert!(matches!(
+            preferred_artifact(&package(&[])),
+            Err(Error::NoSupportedArtifactFormat { .. })
+        ));
+    }
+}
@@ -24,6 +24,10 @@ pub(crate) fn proxy(whitelabel: WhitelabelConfig) -> Result<(), Error> {
     )?;
 
     let project_manifest = ProjectManifest::load(manifest_path.as_path())?;
+    log::debug!(
+        "resolving binary proxy `{binary_name}` with the project manifest at {}",
+        manifest_path.display()
+    );
 
     let Some((installation_id, resolved_path)) = project_manifest
         .products()
@@ -39,14 +43,36 @@ pub(crate) fn proxy(whitelabel: WhitelabelConfig) -> Result<(), Error> {
         return Err(Error::BinaryNotInstalled(binary_name));
     };
 
-    let mut command = Command::new(
-        config
-            .paths
-            .installation_dir
-            .join(installation_id.clone())
-            .join(resolved_path),
+    let binary_path = config
+        .paths
+        .installation_dir
+        .join(installation_id.clone())
+        .join(resolved_path);
+    log::debug!(
+        "binary proxy `{binary_name}` resolved to {} in installation {}",
+        binary_path.display(),
+        installation_id.0
     );
 
+    // Checking the binary before every execution is opt-in, as it requires reading the whole
+    // binary every time it's invoked. The binary is executed by path after the check, so it's not
+    // protected from being replaced in between (see `Config::verify_binaries`).
+    if config.verify_binaries || project_manifest.verify_binaries() {
+        log::debug!("verifying the checksum of {}", binary_path.display());
+        match state.binary_proxy_matches(&installation_id, &binary_name, &binary_path) {
+            Ok(Some(true)) => {}
+            Ok(Some(false)) | Err(_) => {
+                return Err(Error::BinaryModified {
+                    binary: binary_name,
+                    path: binary_path,
+                })
+            }
+            Ok(None) => return Err(Error::BinaryChecksumMissing(binary_name)),
+        }
+    }
+
+    let mut command = Command::new(binary_path);
+
     // In order to ensure, for example, our `cargo` invokes our `rustc` we
     // append the proxy dir to the path.
     //
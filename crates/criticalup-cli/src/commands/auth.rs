@@ -6,12 +6,24 @@ use crate::Context;
 use criticalup_core::download_server_client::DownloadServerClient;
 use criticalup_core::errors::DownloadServerError;
 use criticalup_core::state::State;
+use serde_json::json;
 
 pub(crate) fn run(ctx: &Context) -> Result<(), Error> {
     let state = State::load(&ctx.config)?;
-    let download_server = DownloadServerClient::new(&ctx.config, &state);
+    let download_server = DownloadServerClient::new(&ctx.config, &state)?;
 
     match download_server.get_current_token_data() {
+        Ok(data) if ctx.format.is_json() => {
+            ctx.format.document(json!({
+                "authenticated": true,
+                "token": {
+                    "name": data.name,
+                    "organization_name": data.organization_name,
+                    "expires_at": data.expires_at,
+                },
+            }));
+            Ok(())
+        }
         Ok(data) => {
             eprintln!("valid authentication token present");
             eprintln!();
@@ -24,6 +36,13 @@ pub(crate) fn run(ctx: &Context) -> Result<(), Error> {
 
             Ok(())
         }
+        Err(LibError::DownloadServerError {
+            kind: DownloadServerError::AuthenticationFailed,
+            ..
+        }) if ctx.format.is_json() => {
+            ctx.format.document(json!({ "authenticated": false }));
+            Err(Error::Exit(1))
+        }
         Err(LibError::DownloadServerError {
             kind: DownloadServerError::AuthenticationFailed,
             ..
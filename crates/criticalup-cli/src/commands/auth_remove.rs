@@ -4,14 +4,19 @@
 use crate::errors::Error;
 use crate::Context;
 use criticalup_core::state::State;
+use serde_json::json;
 
 pub(crate) fn run(ctx: &Context) -> Result<(), Error> {
     let state = State::load(&ctx.config)?;
 
-    if state.authentication_token(None).is_some() {
-        state.set_authentication_token(None);
-        state.persist()?;
+    let removed = state.authentication_token(None).is_some();
+    if removed {
+        state.update(&ctx.config, |state| {
+            state.set_authentication_token(None);
+            Ok::<_, Error>(())
+        })?;
     }
 
+    ctx.format.document(json!({ "removed": removed }));
     Ok(())
 }
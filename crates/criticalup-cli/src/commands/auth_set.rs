@@ -7,11 +7,12 @@ use atty::Stream;
 use criticalup_core::download_server_client::DownloadServerClient;
 use criticalup_core::errors::DownloadServerError;
 use criticalup_core::state::{AuthenticationToken, State};
+use serde_json::json;
 use std::io::Write;
 
 pub(crate) fn run(ctx: &Context, token: Option<String>) -> Result<(), Error> {
     let state = State::load(&ctx.config)?;
-    let download_server = DownloadServerClient::new(&ctx.config, &state);
+    let download_server = DownloadServerClient::new(&ctx.config, &state)?;
 
     let token = if let Some(token) = token {
         token
@@ -21,10 +22,25 @@ pub(crate) fn run(ctx: &Context, token: Option<String>) -> Result<(), Error> {
         token_from_stdin_programmatic().map_err(Error::CantReadTokenFromStdin)?
     };
 
+    // The token is only set in memory to check it against the download server, and set again
+    // when the state is updated as updating discards changes that were not persisted.
     state.set_authentication_token(Some(AuthenticationToken::seal(&token)));
 
     match download_server.get_current_token_data() {
-        Ok(_) => Ok(state.persist()?),
+        Ok(data) => {
+            state.update(&ctx.config, |state| {
+                state.set_authentication_token(Some(AuthenticationToken::seal(&token)));
+                Ok::<_, Error>(())
+            })?;
+            ctx.format.document(json!({
+                "token": {
+                    "name": data.name,
+                    "organization_name": data.organization_name,
+                    "expires_at": data.expires_at,
+                },
+            }));
+            Ok(())
+        }
 
         Err(LibError::DownloadServerError {
             kind: DownloadServerError::AuthenticationFailed,
@@ -0,0 +1,86 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use owo_colors::OwoColorize;
+use serde_json::{json, Value};
+
+use criticalup_core::cache::{ByteSize, CacheEntry, DownloadCache};
+use criticalup_core::lock::FileLock;
+
+use crate::errors::Error;
+use crate::Context;
+
+pub(crate) fn list(ctx: &Context) -> Result<(), Error> {
+    let entries = DownloadCache::new(&ctx.config).list()?;
+    if ctx.format.is_json() {
+        ctx.format.document(json!({
+            "archives": entries_json(&entries),
+            "size": total_size(&entries).0,
+        }));
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("{} the download cache is empty", "info:".bold());
+        return Ok(());
+    }
+
+    for entry in &entries {
+        println!("{}  {:<8} {:>10}", entry.sha256, entry.format, entry.size);
+    }
+    println!(
+        "{} {} archives, {} in total",
+        "info:".bold(),
+        entries.len(),
+        total_size(&entries)
+    );
+    Ok(())
+}
+
+pub(crate) fn prune(ctx: &Context, max_size: Option<ByteSize>) -> Result<(), Error> {
+    let max_size = max_size.unwrap_or(ctx.config.cache_max_size);
+    // Archives can't be removed while other processes are installing from them.
+    let _lock = FileLock::cache(&ctx.config)?;
+    let removed = DownloadCache::new(&ctx.config).prune(max_size)?;
+    report_removed(ctx, &removed);
+    Ok(())
+}
+
+pub(crate) fn clear(ctx: &Context) -> Result<(), Error> {
+    let _lock = FileLock::cache(&ctx.config)?;
+    let removed = DownloadCache::new(&ctx.config).clear()?;
+    report_removed(ctx, &removed);
+    Ok(())
+}
+
+fn report_removed(ctx: &Context, removed: &[CacheEntry]) {
+    if ctx.format.is_json() {
+        ctx.format.document(json!({
+            "removed": entries_json(removed),
+            "size": total_size(removed).0,
+        }));
+        return;
+    }
+    println!(
+        "{} removed {} archives from the download cache, freeing {}",
+        "info:".bold(),
+        removed.len(),
+        total_size(removed)
+    );
+}
+
+fn total_size(entries: &[CacheEntry]) -> ByteSize {
+    ByteSize(entries.iter().map(|entry| entry.size.0).sum())
+}
+
+fn entries_json(entries: &[CacheEntry]) -> Vec<Value> {
+    entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "sha256": entry.sha256,
+                "format": entry.format,
+                "size": entry.size.0,
+            })
+        })
+        .collect()
+}
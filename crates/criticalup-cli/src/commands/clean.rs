@@ -4,8 +4,9 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use owo_colors::OwoColorize;
+use serde_json::json;
 
+use criticalup_core::lock::FileLock;
 use criticalup_core::project_manifest::InstallationId;
 use criticalup_core::state::State;
 
@@ -16,15 +17,20 @@ pub(crate) fn run(ctx: &Context) -> Result<(), Error> {
     let installations_dir = &ctx.config.paths.installation_dir;
     let state = State::load(&ctx.config)?;
 
-    delete_unused_installations(installations_dir, &state)?;
-    delete_untracked_installation_dirs(installations_dir, state)?;
+    delete_unused_installations(ctx, installations_dir, &state)?;
+    delete_untracked_installation_dirs(ctx, installations_dir, &state)?;
+    delete_stale_staging_dirs(ctx, installations_dir)?;
 
     Ok(())
 }
 
 /// Deletes installation from `State` wl; ith `InstallationId`s that have empty manifest section, and
 /// deletes the installation directory from the disk if present.
-fn delete_unused_installations(installations_dir: &Path, state: &State) -> Result<(), Error> {
+fn delete_unused_installations(
+    ctx: &Context,
+    installations_dir: &Path,
+    state: &State,
+) -> Result<(), Error> {
     let unused_installations: Vec<InstallationId> = state
         .installations()
         .iter()
@@ -33,30 +39,49 @@ fn delete_unused_installations(installations_dir: &Path, state: &State) -> Resul
         .collect();
 
     if unused_installations.is_empty() {
-        println!("{} no unused installations found", "info:".bold());
+        ctx.format.info("no unused installations found");
         return Ok(());
     }
 
     for installation in unused_installations {
-        println!(
-            "{} deleting unused installation {}",
-            "info:".bold(),
-            installation.0
-        );
+        // Other processes could have started using the installation since the state was loaded,
+        // so whether it's unused is checked again while holding its lock.
+        let _lock = FileLock::installation(&ctx.config, &installation)?;
+        let unused = state.update(&ctx.config, |state| {
+            let unused = state
+                .installations()
+                .get(&installation)
+                .is_some_and(|item| item.manifests().is_empty());
+            if unused {
+                // The state will be saved onto the disk but the removal of the installation
+                // directory will be done after this which may not exist.
+                state.remove_installation(&installation);
+            }
+            Ok::<_, Error>(unused)
+        })?;
+        if !unused {
+            continue;
+        }
 
-        // Remove installation from the state.
-        state.remove_installation(&installation);
-        // The state will be saved onto the disk but the removal of the installation directory
-        // will be done after this which may not exist.
-        state.persist()?;
+        ctx.format.event(
+            "removing_unused_installation",
+            json!({ "installation": installation.0 }),
+            format_args!("deleting unused installation {}", installation.0),
+        );
 
         // Remove installation directory from physical location.
         let installation_dir_to_delete = installations_dir.join(&installation.0);
         if installation_dir_to_delete.exists() {
-            println!(
-                "{} deleting unused installation directory {}",
-                "info:".bold(),
-                &installation_dir_to_delete.display()
+            ctx.format.event(
+                "removing_installation_dir",
+                json!({
+                    "installation": installation.0,
+                    "path": installation_dir_to_delete,
+                }),
+                format_args!(
+                    "deleting unused installation directory {}",
+                    installation_dir_to_delete.display()
+                ),
             );
             fs::remove_dir_all(&installation_dir_to_delete).map_err(|err| {
                 Error::DeletingUnusedInstallationDir {
@@ -71,23 +96,39 @@ fn delete_unused_installations(installations_dir: &Path, state: &State) -> Resul
 
 /// Deletes the installation directories from the disk that do not exist in the State.
 fn delete_untracked_installation_dirs(
+    ctx: &Context,
     installations_dir: &PathBuf,
-    state: State,
+    state: &State,
 ) -> Result<(), Error> {
-    let installations_in_state = state.installations();
     let mut are_untracked_installation_dirs_present = false;
 
     for item_in_installation_dir in fs::read_dir(installations_dir)? {
         let item = item_in_installation_dir?;
         if item.file_type()?.is_dir() {
             let installation_dir_name = item.file_name();
-            if let Some(name) = installation_dir_name.to_str() {
-                if !installations_in_state.contains_key(&InstallationId(name.into())) {
+            // Hidden directories are the staging directories of installations, and the previous
+            // contents of installations being replaced. They're handled separately, as they're
+            // normally removed by the process that created them.
+            if let Some(name) = installation_dir_name
+                .to_str()
+                .filter(|name| !name.starts_with('.'))
+            {
+                let installation_id = InstallationId(name.into());
+                if state.installations().contains_key(&installation_id) {
+                    continue;
+                }
+                // Other processes could have finished installing it since the state was loaded.
+                let _lock = FileLock::installation(&ctx.config, &installation_id)?;
+                state.reload()?;
+                if !state.installations().contains_key(&installation_id) {
                     are_untracked_installation_dirs_present = true;
-                    println!(
-                        "{} deleting untracked installation directory {}",
-                        "info:".bold(),
-                        item.path().to_path_buf().display()
+                    ctx.format.event(
+                        "removing_untracked_installation_dir",
+                        json!({ "path": item.path() }),
+                        format_args!(
+                            "deleting untracked installation directory {}",
+                            item.path().display()
+                        ),
                     );
 
                     fs::remove_dir_all(item.path()).map_err(|err| {
@@ -102,11 +143,69 @@ fn delete_untracked_installation_dirs(
     }
 
     if !are_untracked_installation_dirs_present {
-        println!(
-            "{} no untracked installation directories found",
-            "info:".bold()
-        );
+        ctx.format
+            .info("no untracked installation directories found");
     }
 
     Ok(())
 }
+
+/// Deletes the staging directories and the previous contents of replaced installations left
+/// behind by processes that didn't get to remove them, for example because they were killed or
+/// the machine lost power. Directories of installations locked by another process are in use,
+/// and are left alone.
+fn delete_stale_staging_dirs(ctx: &Context, installations_dir: &Path) -> Result<(), Error> {
+    for item in fs::read_dir(installations_dir)? {
+        let item = item?;
+        if !item.file_type()?.is_dir() {
+            continue;
+        }
+        let name = item.file_name();
+        let Some(installation_id) = name.to_str().and_then(stale_dir_installation) else {
+            continue;
+        };
+        let Some(_lock) = FileLock::try_installation(&ctx.config, &installation_id)? else {
+            continue;
+        };
+
+        ctx.format.event(
+            "removing_stale_staging_dir",
+            json!({ "installation": installation_id.0, "path": item.path() }),
+            format_args!(
+                "deleting leftover staging directory {}",
+                item.path().display()
+            ),
+        );
+        fs::remove_dir_all(item.path()).map_err(|err| Error::DeletingUntrackedInstallationDir {
+            path: item.path(),
+            kind: err,
+        })?;
+    }
+    Ok(())
+}
+
+/// Installation a staging directory (`.{id}-staging-*`) or the previous contents of a replaced
+/// installation (`.{id}-previous-*`) belong to.
+fn stale_dir_installation(name: &str) -> Option<InstallationId> {
+    let name = name.strip_prefix('.')?;
+    ["-staging-", "-previous-"]
+        .iter()
+        .find_map(|marker| name.split_once(marker))
+        .map(|(id, _)| InstallationId(id.into()))
+        .filter(|id| !id.0.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_dir_installation() {
+        let id = |name| stale_dir_installation(name).map(|id| id.0);
+        assert_eq!(Some("abc123".into()), id(".abc123-staging-Xy12"));
+        assert_eq!(Some("abc123".into()), id(".abc123-previous-Xy12"));
+        assert_eq!(None, id("abc123"));
+        assert_eq!(None, id(".abc123"));
+        assert_eq!(None, id(".-staging-Xy12"));
+    }
+}
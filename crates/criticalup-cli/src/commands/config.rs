@@ -0,0 +1,93 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use serde_json::json;
+
+use criticalup_core::config::{ConfigFile, Setting, SETTINGS};
+
+use crate::errors::Error;
+use crate::Context;
+
+pub(crate) fn get(ctx: &Context, key: String) -> Result<(), Error> {
+    let setting = Setting::find(&key)?;
+    let file = ConfigFile::load(&ctx.config.paths.config_file)?;
+    let value = file.get(setting)?;
+    warn_if_overridden(setting);
+
+    if ctx.format.is_json() {
+        ctx.format
+            .document(json!({ "key": setting.key, "value": value }));
+    } else if let Some(value) = &value {
+        println!("{value}");
+    }
+
+    // Like `git config`, exit with an error when the setting is missing to allow scripts to
+    // detect it.
+    match value {
+        Some(_) => Ok(()),
+        None => Err(Error::Exit(1)),
+    }
+}
+
+pub(crate) fn set(ctx: &Context, key: String, value: String) -> Result<(), Error> {
+    let setting = Setting::find(&key)?;
+    let mut file = ConfigFile::load(&ctx.config.paths.config_file)?;
+    file.set(setting, &value)?;
+    file.persist()?;
+    warn_if_overridden(setting);
+
+    ctx.format.document(json!({
+        "key": setting.key,
+        "value": file.get(setting)?,
+    }));
+    Ok(())
+}
+
+pub(crate) fn list(ctx: &Context) -> Result<(), Error> {
+    let file = ConfigFile::load(&ctx.config.paths.config_file)?;
+
+    if ctx.format.is_json() {
+        let mut settings = Vec::new();
+        for setting in SETTINGS {
+            settings.push(json!({
+                "key": setting.key,
+                "env_var": setting.env_var,
+                "file_value": file.get(setting)?,
+                "env_value": setting.env_value(),
+            }));
+        }
+        ctx.format.document(json!({
+            "path": file.path(),
+            "settings": settings,
+        }));
+        return Ok(());
+    }
+
+    let mut empty = true;
+    for setting in SETTINGS {
+        // Environment variables take precedence over the file, so show where the value comes from.
+        match (setting.env_value(), file.get(setting)?) {
+            (Some(value), _) => println!("{} = {value} (from {})", setting.key, setting.env_var),
+            (None, Some(value)) => println!("{} = {value}", setting.key),
+            (None, None) => continue,
+        }
+        empty = false;
+    }
+    if empty {
+        ctx.format.info(format!(
+            "no settings configured in {}",
+            file.path().display()
+        ));
+    }
+    Ok(())
+}
+
+fn warn_if_overridden(setting: &Setting) {
+    if setting.env_value().is_some() {
+        log::warn!(
+            "the {} environment variable overrides the `{}` setting",
+            setting.env_var,
+            setting.key
+        );
+    }
+}
@@ -1,26 +1,41 @@
 // SPDX-FileCopyrightText: The Ferrocene Developers
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-
-use owo_colors::OwoColorize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use criticaltrust::integrity::IntegrityVerifier;
-use criticaltrust::manifests::{Release, ReleaseArtifactFormat};
+use criticaltrust::manifests::{Release, ReleaseArtifact, ReleasePackage};
+use criticalup_core::cache::DownloadCache;
 use criticalup_core::download_server_client::DownloadServerClient;
+use criticalup_core::lock::FileLock;
+use criticalup_core::offline::OfflineDir;
+use criticalup_core::progress::{ProgressReader, ProgressReporter, ProgressStep};
 use criticalup_core::project_manifest::{ProjectManifest, ProjectManifestProduct};
-use criticalup_core::state::State;
-
-use crate::errors::Error;
-use crate::errors::Error::{IntegrityErrorsWhileInstallation, PackageDependenciesNotSupported};
+use criticalup_core::state::{InstallationMetadata, InstallationPackage, State};
+use serde_json::json;
+
+use crate::archive::{preferred_artifact, unpack_and_verify};
+use crate::errors::Error::IntegrityErrorsWhileInstallation;
+use crate::errors::{Error, LibError};
+use crate::progress::InstallProgress;
+use crate::reuse::{find_installed_package, installed_package_in, is_intact, link_and_verify};
+use crate::staging::StagingDir;
 use crate::Context;
 
-pub const DEFAULT_RELEASE_ARTIFACT_FORMAT: ReleaseArtifactFormat = ReleaseArtifactFormat::TarXz;
-
-pub(crate) fn run(ctx: &Context, project: Option<PathBuf>) -> Result<(), Error> {
-    // TODO: If `std::io::stdout().is_terminal() == true``, provide a nice, fancy progress bar using indicatif.
-    //       Retain existing behavior to support non-TTY usage.
-
+pub(crate) fn run(
+    ctx: &Context,
+    project: Option<PathBuf>,
+    jobs: Option<NonZeroUsize>,
+    offline_dir: Option<PathBuf>,
+    force: bool,
+) -> Result<(), Error> {
     let state = State::load(&ctx.config)?;
 
     // Get manifest location if arg `project` is None
@@ -30,12 +45,25 @@ pub(crate) fn run(ctx: &Context, project: Option<PathBuf>) -> Result<(), Error>
     let manifest = ProjectManifest::get(project)?;
 
     let installation_dir = &ctx.config.paths.installation_dir;
+    let jobs = jobs.unwrap_or(ctx.config.jobs);
+    let client = match offline_dir {
+        Some(dir) => DownloadServerClient::offline(&ctx.config, &state, OfflineDir::new(dir))?,
+        None => DownloadServerClient::new(&ctx.config, &state)?,
+    };
 
     for product in manifest.products() {
         let abs_installation_dir_path = installation_dir.join(product.installation_id());
-
-        if !abs_installation_dir_path.exists() {
-            install_product_afresh(ctx, &state, &manifest_path, product)?;
+        // Other processes could be installing or removing the same installation.
+        let _lock = FileLock::installation(&ctx.config, &product.installation_id())?;
+        state.reload()?;
+
+        if force {
+            // The existing installation is only replaced once the new one is verified.
+            let mode = InstallMode::Force;
+            install_product(ctx, &state, &client, &manifest_path, product, jobs, mode)?;
+        } else if !abs_installation_dir_path.exists() {
+            let mode = InstallMode::Install;
+            install_product(ctx, &state, &client, &manifest_path, product, jobs, mode)?;
         } else {
             // Check if the state file has no mention of this installation.
             let does_this_installation_exist_in_state = state
@@ -44,44 +72,103 @@ pub(crate) fn run(ctx: &Context, project: Option<PathBuf>) -> Result<(), Error>
             if !does_this_installation_exist_in_state {
                 // If the installation directory exists, but the State has no installation of that
                 // InstallationId, then re-run the install command and go through installation.
-                install_product_afresh(ctx, &state, &manifest_path, product)?;
+                let mode = InstallMode::Install;
+                install_product(ctx, &state, &client, &manifest_path, product, jobs, mode)?;
             } else {
                 // If the installation directory exists AND there is an existing installation with
                 // that InstallationId, then merely update the installation in the State file to
                 // reflect this manifest/project.
-                state.update_installation_manifests(&product.installation_id(), &manifest_path)?;
-                println!("Skipping installation for product '{}' because it seems to be already installed.\n\
-                    If you want to reinstall it, please run 'criticalup remove' followed by 'criticalup install' command.",
-                         product.name());
+                state.update(&ctx.config, |state| {
+                    state.update_installation_manifests(&product.installation_id(), &manifest_path)
+                })?;
+                ctx.format.json_event(
+                    "product_already_installed",
+                    json!({
+                        "product": product.name(),
+                        "release": product.release(),
+                        "installation": product.installation_id().0,
+                    }),
+                );
+                if !ctx.format.is_json() {
+                    println!("Skipping installation for product '{}' because it seems to be already installed.\n\
+                        If you want to reinstall it, please run 'criticalup install --force'.",
+                             product.name());
+                }
             }
         }
-        // Even though we do not install the existing packages again, we still need to add
-        // the manifest to the state.json.
-        state.persist()?;
     }
 
-    criticalup_core::binary_proxies::update(&ctx.config, &state, &std::env::current_exe()?)?;
+    // The state is locked while updating the binary proxies, so that they match all the
+    // installations even when other processes are installing at the same time.
+    state.update(&ctx.config, |state| -> Result<(), Error> {
+        criticalup_core::binary_proxies::update(&ctx.config, state, &std::env::current_exe()?)?;
+        Ok(())
+    })?;
+
+    // Archives downloaded by this installation are the most recently used, so they're kept. The
+    // cache is left alone while other processes use it, the next installation will prune it.
+    match FileLock::try_cache(&ctx.config)? {
+        Some(_lock) => {
+            DownloadCache::new(&ctx.config).prune(ctx.config.cache_max_size)?;
+        }
+        None => log::debug!("not pruning the download cache, as another process is using it"),
+    }
 
     Ok(())
 }
 
-fn install_product_afresh(
+/// How [`install_product`] treats the packages that are already installed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InstallMode {
+    /// Reuse the packages of any existing installation.
+    Install,
+    /// Download all packages again, even if they're already installed.
+    Force,
+    /// Reuse the packages of the product's own installation that are intact, and download again
+    /// only the ones whose files fail the integrity checks.
+    Repair,
+}
+
+/// Install `product` in a staging directory, replacing its installation once all of its packages
+/// are downloaded and verified. Callers must hold the lock on the installation.
+pub(crate) fn install_product(
     ctx: &Context,
     state: &State,
+    client: &DownloadServerClient,
     manifest_path: &Path,
     product: &ProjectManifestProduct,
+    jobs: NonZeroUsize,
+    mode: InstallMode,
 ) -> Result<(), Error> {
     let product_name = product.name();
     let release = product.release();
     let installation_dir = &ctx.config.paths.installation_dir;
     let abs_installation_dir_path = installation_dir.join(product.installation_id());
-    let client = DownloadServerClient::new(&ctx.config, state);
+    let start = Instant::now();
     let keys = client.get_keys()?;
 
-    // TODO: Add tracing to support log levels, structured logging.
-    println!(
-        "{} installing product '{product_name}' ({release})",
-        "info:".bold()
+    // Progress bars are only shown when stdout is a terminal, otherwise the output stays plain.
+    let progress = Arc::new(InstallProgress::new(ctx.format));
+    let fields = |extra: serde_json::Value| {
+        let mut fields = json!({
+            "product": product_name,
+            "release": release,
+            "installation": product.installation_id().0,
+        });
+        if let (Some(fields), serde_json::Value::Object(extra)) = (fields.as_object_mut(), extra) {
+            fields.extend(extra);
+        }
+        fields
+    };
+
+    let (verb, event) = match mode {
+        InstallMode::Install | InstallMode::Force => ("installing", "installing_product"),
+        InstallMode::Repair => ("repairing", "repairing_product"),
+    };
+    progress.event(
+        event,
+        fields(json!({})),
+        format_args!("{verb} product '{product_name}' ({release})"),
     );
 
     let mut integrity_verifier = IntegrityVerifier::new(&keys);
@@ -91,128 +178,472 @@ fn install_product_afresh(
         client.get_product_release_manifest(product_name, product.release())?;
     let verified_release_manifest = release_manifest_from_server.signed.into_verified(&keys)?;
 
-    // criticalup 0.1, return error if any of package.dependencies is not empty.
-    // We have to use manifest's Release because the information about dependencies
-    // only lives in it and not in product's packages which is only a name/String.
-    check_for_package_dependencies(&verified_release_manifest)?;
-
     let release_name = verified_release_manifest.release.as_str();
 
-    product.create_product_dir(&ctx.config.paths.installation_dir)?;
+    // The dependencies of the packages in the project manifest are only listed in the release
+    // manifest, so they're installed alongside the requested packages.
+    let mut reused = Vec::new();
+    let mut packages = Vec::new();
+    let mut metadata_packages = Vec::new();
+    for release_package in resolve_dependencies(&verified_release_manifest, product.packages())? {
+        let package = release_package.package.as_str();
+        let artifact = preferred_artifact(release_package)?;
+        metadata_packages.push(InstallationPackage::new(package, artifact));
+        let installed = match mode {
+            // Packages already installed by other installations (for example before a package
+            // was added to the project manifest) are reused rather than downloaded again.
+            InstallMode::Install => find_installed_package(
+                &ctx.config,
+                state,
+                &keys,
+                &verified_release_manifest,
+                package,
+            ),
+            InstallMode::Force => None,
+            InstallMode::Repair => installed_package_in(
+                &abs_installation_dir_path,
+                &keys,
+                &verified_release_manifest,
+                package,
+            )
+            .filter(|installed| is_intact(installed, &keys)),
+        };
+        match installed {
+            Some(installed) => {
+                if mode == InstallMode::Install {
+                    progress.event(
+                        "reusing_package",
+                        fields(json!({ "package": package })),
+                        format_args!(
+                            "reusing component '{package}' for '{product_name}' ({release}) from an existing installation"
+                        ),
+                    );
+                }
+                reused.push(installed);
+            }
+            None => packages.push((package, artifact)),
+        }
+    }
 
-    for package in product.packages() {
-        println!(
-            "{} downloading component '{package}' for '{product_name}' ({release})",
-            "info:".bold()
+    let installation_id = product.installation_id();
+    if mode == InstallMode::Repair
+        && packages.is_empty()
+        && state.installations().contains_key(&installation_id)
+    {
+        progress.event(
+            "product_intact",
+            fields(json!({})),
+            format_args!("all components of product '{product_name}' ({release}) are intact"),
         );
+        state.update(&ctx.config, |state| {
+            state.update_installation_manifests(&installation_id, manifest_path)
+        })?;
+        return Ok(());
+    }
 
-        let response_file = client.download_package(
-            product_name,
-            release_name,
-            package,
-            DEFAULT_RELEASE_ARTIFACT_FORMAT,
-        )?;
-
-        // Archive file path, path with the archive extension.
-        let package_name_with_extension =
-            format!("{}.{}", package, DEFAULT_RELEASE_ARTIFACT_FORMAT);
-        let abs_artifact_compressed_file_path: PathBuf =
-            abs_installation_dir_path.join(&package_name_with_extension);
-
-        // Save the downloaded package archive on disk.
-        std::fs::write(&abs_artifact_compressed_file_path, response_file.clone())?;
+    // Packages are unpacked in a staging directory, which is only moved into place once all of
+    // them are verified. Any failure (or Ctrl-C) before then removes the staging directory.
+    let staging = StagingDir::new(&abs_installation_dir_path)?;
+    for installed in &reused {
+        link_and_verify(installed, staging.path(), &mut integrity_verifier)?;
+    }
 
-        println!(
-            "{} installing component '{package}' for '{product_name}' ({release})",
-            "info:".bold()
+    for (package, _) in &packages {
+        progress.event(
+            "downloading_package",
+            fields(json!({ "package": package })),
+            format_args!("downloading component '{package}' for '{product_name}' ({release})"),
         );
-
-        let decoder = xz2::read::XzDecoder::new(response_file.as_slice());
-        let mut archive = tar::Archive::new(decoder);
-        archive.set_preserve_permissions(true);
-        archive.set_preserve_mtime(true);
-        archive.set_unpack_xattrs(true);
-
-        let entries = archive.entries()?;
-        for each in entries {
-            let mut entry = each?;
-
-            let p = entry.path()?.into_owned();
-            let entry_path_on_disk = abs_installation_dir_path.join(p);
-            entry.unpack(&entry_path_on_disk)?;
-
-            if entry_path_on_disk.is_file() {
-                integrity_verifier.add(
-                    &entry_path_on_disk,
-                    entry.header().mode()?,
-                    &std::fs::read(&entry_path_on_disk)?,
-                );
-            }
-        }
-
-        clean_archive_download(&abs_artifact_compressed_file_path)?;
     }
 
+    // Archives are downloaded concurrently, but they're unpacked one at a time in the order of
+    // the project manifest, as the integrity verifier needs to see all the files. Other processes
+    // can't prune the cache until all the archives are unpacked.
+    let cache_lock = FileLock::cache_shared(&ctx.config)?;
+    let downloader = client.package_downloader().with_progress(progress.clone());
+    let download = |(package, artifact): &(&str, &ReleaseArtifact)| {
+        // The archive is verified against the release manifest while it's downloaded, so
+        // nothing is extracted from an archive that doesn't match the signed size and checksum.
+        downloader.download_package_artifact(product_name, release_name, package, artifact)
+    };
+    download_concurrently(
+        &packages,
+        jobs,
+        download,
+        |(package, artifact), archive_path| {
+            progress.event(
+                "installing_package",
+                fields(json!({ "package": package })),
+                format_args!("installing component '{package}' for '{product_name}' ({release})"),
+            );
+
+            progress.start(ProgressStep::Unpack, package, artifact.size as u64);
+            let archive = ProgressReader::new(
+                BufReader::new(File::open(archive_path)?),
+                progress.as_ref(),
+                ProgressStep::Unpack,
+                package,
+            );
+            unpack_and_verify(
+                archive,
+                &artifact.format,
+                staging.path(),
+                &mut integrity_verifier,
+            )?;
+            progress.finish(ProgressStep::Unpack, package);
+            Ok(())
+        },
+    )?;
+    drop(cache_lock);
+
     let verified_packages = integrity_verifier
         .verify()
         .map_err(IntegrityErrorsWhileInstallation)?;
-
-    state.add_installation(
-        &product.installation_id(),
-        &verified_packages,
-        manifest_path,
-        &ctx.config,
-    )?;
+    staging.persist(&abs_installation_dir_path)?;
+
+    let metadata = InstallationMetadata {
+        product: product_name.into(),
+        release: release_name.into(),
+        commit: verified_release_manifest.commit.clone(),
+        packages: metadata_packages,
+        installed_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0),
+        criticalup_version: env!("CARGO_PKG_VERSION").into(),
+        size: dir_size(&abs_installation_dir_path)?,
+    };
+    state.update(&ctx.config, |state| {
+        state.add_installation(
+            &installation_id,
+            &verified_packages,
+            manifest_path,
+            &ctx.config,
+        )?;
+        state.set_installation_metadata(&installation_id, metadata)
+    })?;
+    log::info!(
+        "installed product '{product_name}' ({release}) in {:.1?}",
+        start.elapsed()
+    );
+    ctx.format.json_event(
+        "product_installed",
+        fields(json!({ "path": abs_installation_dir_path })),
+    );
     Ok(())
 }
 
-fn check_for_package_dependencies(verified_release_manifest: &Release) -> Result<(), Error> {
-    for package in verified_release_manifest.packages.iter() {
-        if !package.dependencies.is_empty() {
-            return Err(PackageDependenciesNotSupported(package.package.clone()));
+/// Total size of the files in `path` and its subdirectories, without following symlinks.
+///
+/// Files hard linked multiple times in `path` are only counted once. Files shared with other
+/// installations (when packages are reused) are counted in the size of each of them.
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    fn walk(path: &Path, seen: &mut HashSet<(u64, u64)>) -> std::io::Result<u64> {
+        let mut size = 0;
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                size += walk(&entry.path(), seen)?;
+            } else if first_link(&metadata, seen) {
+                size += metadata.len();
+            }
         }
+        Ok(size)
     }
-    Ok(())
+    walk(path, &mut HashSet::new())
 }
 
-fn clean_archive_download(abs_artifact_compressed_file_path: &PathBuf) -> Result<(), Error> {
-    std::fs::remove_file(abs_artifact_compressed_file_path)?;
-    Ok(())
+/// Whether `metadata` belongs to a file not seen before, based on its device and inode numbers.
+#[cfg(unix)]
+fn first_link(metadata: &std::fs::Metadata, seen: &mut HashSet<(u64, u64)>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() == 1 || seen.insert((metadata.dev(), metadata.ino()))
 }
 
-#[test]
-fn dependencies_check() {
-    use criticaltrust::manifests::ReleasePackage;
+#[cfg(not(unix))]
+fn first_link(_metadata: &std::fs::Metadata, _seen: &mut HashSet<(u64, u64)>) -> bool {
+    // Hard links can't be detected without unstable APIs on other platforms.
+    true
+}
+
+/// Download all `items` using up to `jobs` threads, calling `process` on the main thread with the
+/// path of each downloaded file, in the same order as `items`.
+///
+/// Downloaded files are kept in the download cache, so they're not removed once processed. If any
+/// download or processing fails, no new downloads are started.
+pub(crate) fn download_concurrently<T: Sync>(
+    items: &[T],
+    jobs: NonZeroUsize,
+    download: impl Fn(&T) -> Result<PathBuf, LibError> + Sync,
+    mut process: impl FnMut(&T, &Path) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let next = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.get().min(items.len()) {
+            let sender = sender.clone();
+            let (next, cancelled, download) = (&next, &cancelled, &download);
+            scope.spawn(move || {
+                while !cancelled.load(Ordering::SeqCst) {
+                    let idx = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(item) = items.get(idx) else { break };
+                    if sender.send((idx, download(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut completed = HashMap::new();
+        let mut process_all = || {
+            for (idx, item) in items.iter().enumerate() {
+                let path = loop {
+                    if let Some(result) = completed.remove(&idx) {
+                        break result?;
+                    }
+                    let (done, result) = receiver.recv().expect("download threads exited early");
+                    completed.insert(done, result);
+                };
+                process(item, &path)?;
+            }
+            Ok(())
+        };
+        let result = process_all();
+
+        if result.is_err() {
+            cancelled.store(true, Ordering::SeqCst);
+            // Wait for the downloads in progress, which are kept in the cache for the next time.
+            for _ in receiver.iter() {}
+        }
+        result
+    })
+}
 
-    let dependencies = vec!["dependency_a".to_string()];
+/// Resolve the packages of `release` to install for the `requested` ones, including all their
+/// transitive dependencies. Every package is returned once, after all of its dependencies.
+pub(crate) fn resolve_dependencies<'a>(
+    release: &'a Release,
+    requested: &[String],
+) -> Result<Vec<&'a ReleasePackage>, Error> {
+    fn visit<'a>(
+        release: &'a Release,
+        package: &str,
+        required_by: Option<&str>,
+        stack: &mut Vec<&'a str>,
+        resolved: &mut Vec<&'a ReleasePackage>,
+    ) -> Result<(), Error> {
+        if resolved.iter().any(|p| p.package == package) {
+            return Ok(());
+        }
+        if let Some(start) = stack.iter().position(|p| *p == package) {
+            let mut cycle: Vec<String> = stack[start..].iter().map(|p| p.to_string()).collect();
+            cycle.push(package.into());
+            return Err(Error::PackageDependencyCycle {
+                product: release.product.clone(),
+                release: release.release.clone(),
+                cycle,
+            });
+        }
 
-    let good = Release {
-        product: "ferrocene".to_string(),
-        release: "nightly-2024-02-28".to_string(),
-        commit: "123".to_string(),
-        packages: vec![ReleasePackage {
-            package: "awesome".to_string(),
-            artifacts: vec![],
-            dependencies: vec![],
-        }],
-    };
+        let Some(release_package) = release.packages.iter().find(|p| p.package == package) else {
+            return Err(match required_by {
+                Some(dependent) => Error::PackageDependencyNotFound {
+                    product: release.product.clone(),
+                    release: release.release.clone(),
+                    package: dependent.into(),
+                    dependency: package.into(),
+                },
+                None => Error::PackageNotFoundInRelease {
+                    product: release.product.clone(),
+                    release: release.release.clone(),
+                    package: package.into(),
+                },
+            });
+        };
+
+        stack.push(&release_package.package);
+        for dependency in &release_package.dependencies {
+            visit(
+                release,
+                dependency,
+                Some(&release_package.package),
+                stack,
+                resolved,
+            )?;
+        }
+        stack.pop();
+        resolved.push(release_package);
+        Ok(())
+    }
 
-    assert!(check_for_package_dependencies(&good).is_ok());
+    let mut resolved = Vec::new();
+    for package in requested {
+        visit(release, package, None, &mut Vec::new(), &mut resolved)?;
+    }
+    Ok(resolved)
+}
 
-    let bad = Release {
+#[cfg(test)]
+fn release_with_dependencies(packages: &[(&str, &[&str])]) -> Release {
+    Release {
         product: "ferrocene".to_string(),
         release: "nightly-2024-02-28".to_string(),
         commit: "123".to_string(),
-        packages: vec![ReleasePackage {
-            package: "awesome".to_string(),
-            artifacts: vec![],
-            dependencies,
-        }],
-    };
+        packages: packages
+            .iter()
+            .map(|(package, dependencies)| ReleasePackage {
+                package: package.to_string(),
+                artifacts: vec![],
+                dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+fn resolved_names(release: &Release, requested: &[&str]) -> Result<Vec<String>, Error> {
+    let requested: Vec<String> = requested.iter().map(|p| p.to_string()).collect();
+    Ok(resolve_dependencies(release, &requested)?
+        .into_iter()
+        .map(|p| p.package.clone())
+        .collect())
+}
+
+#[test]
+fn dependencies_are_resolved_transitively() {
+    let release = release_with_dependencies(&[
+        ("cargo", &["rustc"]),
+        ("clippy", &["cargo", "rustc"]),
+        ("rustc", &["rust-std"]),
+        ("rust-std", &[]),
+        ("rust-src", &[]),
+    ]);
+
+    assert_eq!(
+        vec!["rust-src"],
+        resolved_names(&release, &["rust-src"]).unwrap()
+    );
+    assert_eq!(
+        vec!["rust-std", "rustc", "cargo", "clippy"],
+        resolved_names(&release, &["clippy"]).unwrap()
+    );
+    // Packages requested explicitly and as dependencies are only installed once.
+    assert_eq!(
+        vec!["rust-std", "rustc", "rust-src", "cargo"],
+        resolved_names(&release, &["rustc", "rust-src", "cargo", "rust-std"]).unwrap()
+    );
+}
+
+#[test]
+fn dependencies_missing_from_the_release() {
+    let release = release_with_dependencies(&[("cargo", &["rustc"]), ("rustc", &["llvm"])]);
 
-    assert!(check_for_package_dependencies(&bad).is_err());
     assert!(matches!(
-        check_for_package_dependencies(&bad),
-        Err(PackageDependenciesNotSupported(..))
+        resolved_names(&release, &["cargo"]),
+        Err(Error::PackageDependencyNotFound { package, dependency, .. })
+            if package == "rustc" && dependency == "llvm"
     ));
+    assert!(matches!(
+        resolved_names(&release, &["clippy"]),
+        Err(Error::PackageNotFoundInRelease { package, .. }) if package == "clippy"
+    ));
+}
+
+#[test]
+fn dependency_cycles_are_rejected() {
+    let release = release_with_dependencies(&[
+        ("cargo", &["rustc"]),
+        ("rustc", &["rust-std"]),
+        ("rust-std", &["cargo"]),
+        ("clippy", &["clippy"]),
+    ]);
+
+    let err = resolved_names(&release, &["cargo"]).unwrap_err();
+    assert!(matches!(
+        &err,
+        Error::PackageDependencyCycle { cycle, .. }
+            if *cycle == ["cargo", "rustc", "rust-std", "cargo"]
+    ));
+    assert_eq!(
+        "the dependencies of release nightly-2024-02-28 of ferrocene contain a cycle: \
+            cargo -> rustc -> rust-std -> cargo",
+        err.to_string()
+    );
+    assert!(matches!(
+        resolved_names(&release, &["clippy"]),
+        Err(Error::PackageDependencyCycle { cycle, .. }) if *cycle == ["clippy", "clippy"]
+    ));
+}
+
+#[test]
+fn download_concurrently_processes_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let items: Vec<usize> = (0..20).collect();
+
+    let mut processed = Vec::new();
+    download_concurrently(
+        &items,
+        NonZeroUsize::new(4).unwrap(),
+        |item| {
+            // Make later items finish first.
+            std::thread::sleep(std::time::Duration::from_millis(20 - *item as u64));
+            let path = dir.path().join(item.to_string());
+            std::fs::write(&path, item.to_string()).unwrap();
+            Ok(path)
+        },
+        |item, path| {
+            assert_eq!(item.to_string(), std::fs::read_to_string(path).unwrap());
+            processed.push(*item);
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(items, processed);
+}
+
+#[test]
+fn download_concurrently_stops_on_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let items: Vec<usize> = (0..20).collect();
+
+    let mut processed = Vec::new();
+    let result = download_concurrently(
+        &items,
+        NonZeroUsize::new(4).unwrap(),
+        |item| {
+            if *item == 5 {
+                return Err(LibError::CouldNotDetectRootDirectory);
+            }
+            let path = dir.path().join(item.to_string());
+            std::fs::write(&path, b"").unwrap();
+            Ok(path)
+        },
+        |item, _path| {
+            processed.push(*item);
+            Ok(())
+        },
+    );
+
+    assert!(matches!(
+        result,
+        Err(Error::Lib(LibError::CouldNotDetectRootDirectory))
+    ));
+    assert_eq!(vec![0, 1, 2, 3, 4], processed);
+}
+
+#[test]
+fn dir_size_counts_hard_linked_files_once() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("bin")).unwrap();
+    std::fs::write(dir.path().join("bin/a"), b"0123456789").unwrap();
+    std::fs::write(dir.path().join("b"), b"01234").unwrap();
+    std::fs::hard_link(dir.path().join("bin/a"), dir.path().join("c")).unwrap();
+
+    let expected = if cfg!(unix) { 15 } else { 25 };
+    assert_eq!(expected, dir_size(dir.path()).unwrap());
 }
@@ -0,0 +1,73 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use serde_json::json;
+
+use criticalup_core::cache::ByteSize;
+use criticalup_core::state::State;
+
+use crate::errors::Error;
+use crate::Context;
+
+pub(crate) fn run(ctx: &Context) -> Result<(), Error> {
+    let state = State::load(&ctx.config)?;
+    let installations = state.installations();
+
+    if ctx.format.is_json() {
+        let installations = installations
+            .iter()
+            .map(|(id, installation)| {
+                let metadata = installation.metadata();
+                json!({
+                    "id": id.0,
+                    "path": ctx.config.paths.installation_dir.join(&id.0),
+                    "product": metadata.map(|m| &m.product),
+                    "release": metadata.map(|m| &m.release),
+                    "size": metadata.map(|m| m.size),
+                    "manifests": installation
+                        .manifests()
+                        .iter()
+                        .map(|manifest| json!({
+                            "path": manifest,
+                            "exists": manifest.exists(),
+                        }))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+        ctx.format
+            .document(json!({ "installations": installations }));
+        return Ok(());
+    }
+
+    if installations.is_empty() {
+        ctx.format.info("no installations found");
+        return Ok(());
+    }
+
+    for (id, installation) in installations.iter() {
+        println!("{}", id.0);
+        // Installations created by older releases of criticalup don't have metadata.
+        match installation.metadata() {
+            Some(metadata) => {
+                println!("  product:   {} ({})", metadata.product, metadata.release);
+                println!("  size:      {}", ByteSize(metadata.size));
+            }
+            None => {
+                println!("  product:   unknown");
+                println!("  size:      unknown");
+            }
+        }
+
+        let mut label = "manifests:";
+        if installation.manifests().is_empty() {
+            println!("  {label} none, remove it with `criticalup clean`");
+        }
+        for manifest in installation.manifests() {
+            let missing = if manifest.exists() { "" } else { " (missing)" };
+            println!("  {label:<10} {}{missing}", manifest.display());
+            label = "";
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use criticaltrust::manifests::{ReleaseArtifact, ReleaseArtifactFormat};
+use criticalup_core::download_server_client::DownloadServerClient;
+use criticalup_core::offline::OfflineDir;
+use criticalup_core::state::State;
+use serde_json::json;
+
+use crate::commands::install::{download_concurrently, resolve_dependencies};
+use crate::errors::Error;
+use crate::progress::InstallProgress;
+use crate::Context;
+
+pub(crate) fn run(
+    ctx: &Context,
+    product: String,
+    release: String,
+    packages: Vec<String>,
+    out: PathBuf,
+    jobs: Option<NonZeroUsize>,
+) -> Result<(), Error> {
+    let state = State::load(&ctx.config)?;
+    let client = DownloadServerClient::new(&ctx.config, &state)?;
+    let offline_dir = OfflineDir::new(out);
+    let jobs = jobs.unwrap_or(ctx.config.jobs);
+    let progress = Arc::new(InstallProgress::new(ctx.format));
+
+    progress.event(
+        "mirroring_product",
+        json!({ "product": product, "release": release }),
+        format_args!("mirroring product '{product}' ({release})"),
+    );
+
+    // Everything is verified before being written to the mirror, but the mirror contains the
+    // signed manifests as they were received: offline installations verify them again.
+    let keys_manifest = client.get_keys_manifest()?;
+    let keys = client.get_keys()?;
+    let release_manifest = client.get_product_release_manifest(&product, &release)?;
+    let verified_release = release_manifest.signed.clone().into_verified(&keys)?;
+    // The mirror is laid out after the signed release, so it matches what was verified.
+    let release = &verified_release.release;
+
+    // Dependencies of the selected packages are mirrored too, as they're needed to install them.
+    let selected = if packages.is_empty() {
+        verified_release.packages.iter().collect()
+    } else {
+        resolve_dependencies(&verified_release, &packages)?
+    };
+
+    let mut artifacts = Vec::new();
+    for package in selected {
+        // Artifacts in formats unknown to this release of criticalup can't be downloaded.
+        for artifact in &package.artifacts {
+            if artifact.format != ReleaseArtifactFormat::Unknown {
+                artifacts.push((package.package.as_str(), artifact));
+            }
+        }
+    }
+    for (package, artifact) in &artifacts {
+        progress.event(
+            "downloading_package",
+            json!({
+                "product": product,
+                "release": release,
+                "package": package,
+                "format": artifact.format.to_string(),
+            }),
+            format_args!(
+                "downloading component '{package}' ({}) for '{product}' ({release})",
+                artifact.format
+            ),
+        );
+    }
+
+    // Archives are downloaded straight into the mirror rather than through the download cache,
+    // as they're not installed on this machine.
+    let downloader = client.package_downloader().with_progress(progress.clone());
+    download_concurrently(
+        &artifacts,
+        jobs,
+        |(package, artifact): &(&str, &ReleaseArtifact)| {
+            let dest = offline_dir.package_archive(&product, release, package, &artifact.format);
+            downloader.download_package_artifact_to(&product, release, package, artifact, &dest)?;
+            Ok(dest)
+        },
+        |_, _| Ok(()),
+    )?;
+
+    // The manifests are written last, so that an interrupted mirror doesn't look complete.
+    offline_dir.write_json(
+        &offline_dir.release_manifest(&product, release),
+        &release_manifest,
+    )?;
+    offline_dir.write_json(&offline_dir.keys_manifest(), &keys_manifest)?;
+
+    progress.event(
+        "product_mirrored",
+        json!({ "product": product, "release": release, "path": offline_dir.root() }),
+        format_args!(
+            "mirrored product '{product}' ({release}) to {}, install it with `{} install --offline --from {}`",
+            offline_dir.root().display(),
+            ctx.config.whitelabel.name,
+            offline_dir.root().display(),
+        ),
+    );
+    Ok(())
+}
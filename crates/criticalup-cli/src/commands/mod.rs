@@ -4,8 +4,15 @@
 pub(crate) mod auth;
 pub(crate) mod auth_remove;
 pub(crate) mod auth_set;
+pub(crate) mod cache;
 pub(crate) mod clean;
+pub(crate) mod config;
 pub(crate) mod install;
+pub(crate) mod list;
+pub(crate) mod mirror;
 pub(crate) mod remove;
+pub(crate) mod repair;
 pub(crate) mod run;
+pub(crate) mod show;
+pub(crate) mod verify;
 pub(crate) mod which;
@@ -3,9 +3,11 @@
 
 use crate::errors::Error;
 use crate::Context;
+use criticalup_core::lock::FileLock;
 use criticalup_core::project_manifest::ProjectManifest;
 use criticalup_core::state::State;
-use owo_colors::OwoColorize;
+use serde_json::json;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -14,15 +16,53 @@ pub(crate) fn run(ctx: &Context, project: Option<PathBuf>) -> Result<(), Error>
     let manifest_path = ProjectManifest::discover_canonical_path(project.as_deref())?;
     let installation_dir = &ctx.config.paths.installation_dir;
 
-    let installations_from_which_manifest_was_deleted =
-        state.remove_manifest_from_all_installations(&manifest_path)?;
-    state.persist()?;
+    // The installations are locked before the manifest is removed from them, so that other
+    // processes can't start using them again until they're deleted.
+    let mut locks = BTreeMap::new();
+    let used_by_manifest: Vec<_> = state
+        .installations()
+        .iter()
+        .filter(|(_, installation)| installation.manifests().contains(&manifest_path))
+        .map(|(id, _)| id.clone())
+        .collect();
+    for installation_id in used_by_manifest {
+        let lock = FileLock::installation(&ctx.config, &installation_id)?;
+        locks.insert(installation_id, lock);
+    }
+
+    let installations_from_which_manifest_was_deleted = state.update(&ctx.config, |state| {
+        state.remove_manifest_from_all_installations(&manifest_path)
+    })?;
 
     for installation_id in &installations_from_which_manifest_was_deleted {
-        println!(
-            "{} deleting installation {}",
-            "info:".bold(),
-            installation_id.0
+        // Another process could have added the manifest to an installation after the locks
+        // were taken.
+        if !locks.contains_key(installation_id) {
+            let lock = FileLock::installation(&ctx.config, installation_id)?;
+            locks.insert(installation_id.clone(), lock);
+            state.reload()?;
+        }
+
+        let still_used = state
+            .installations()
+            .get(installation_id)
+            .is_some_and(|installation| !installation.manifests().is_empty());
+        if still_used {
+            ctx.format.event(
+                "keeping_installation",
+                json!({ "installation": installation_id.0 }),
+                format_args!(
+                    "keeping installation {}, as other projects still use it",
+                    installation_id.0
+                ),
+            );
+            continue;
+        }
+
+        ctx.format.event(
+            "removing_installation",
+            json!({ "installation": installation_id.0 }),
+            format_args!("deleting installation {}", installation_id.0),
         );
         let installation_path = installation_dir.join(installation_id.0.as_str());
         if installation_path.exists() {
@@ -31,10 +71,8 @@ pub(crate) fn run(ctx: &Context, project: Option<PathBuf>) -> Result<(), Error>
     }
 
     if installations_from_which_manifest_was_deleted.is_empty() {
-        println!(
-            "{} no existing installations found to be deleted",
-            "info:".bold()
-        );
+        ctx.format
+            .info("no existing installations found to be deleted");
     }
 
     Ok(())
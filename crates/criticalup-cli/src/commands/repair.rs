@@ -0,0 +1,42 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use criticalup_core::download_server_client::DownloadServerClient;
+use criticalup_core::lock::FileLock;
+use criticalup_core::project_manifest::ProjectManifest;
+use criticalup_core::state::State;
+
+use crate::commands::install::{install_product, InstallMode};
+use crate::errors::Error;
+use crate::Context;
+
+pub(crate) fn run(
+    ctx: &Context,
+    project: Option<PathBuf>,
+    jobs: Option<NonZeroUsize>,
+) -> Result<(), Error> {
+    let state = State::load(&ctx.config)?;
+    let manifest_path = ProjectManifest::discover_canonical_path(project.as_deref())?;
+    let manifest = ProjectManifest::get(project)?;
+    let jobs = jobs.unwrap_or(ctx.config.jobs);
+    let client = DownloadServerClient::new(&ctx.config, &state)?;
+
+    // Products that are not installed at all are installed from scratch.
+    for product in manifest.products() {
+        let _lock = FileLock::installation(&ctx.config, &product.installation_id())?;
+        state.reload()?;
+
+        let mode = InstallMode::Repair;
+        install_product(ctx, &state, &client, &manifest_path, product, jobs, mode)?;
+    }
+
+    state.update(&ctx.config, |state| -> Result<(), Error> {
+        criticalup_core::binary_proxies::update(&ctx.config, state, &std::env::current_exe()?)?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use owo_colors::OwoColorize;
+use serde_json::json;
+use std::path::PathBuf;
+
+use criticalup_core::project_manifest::ProjectManifest;
+use criticalup_core::state::State;
+
+use crate::errors::Error;
+use crate::Context;
+
+pub(crate) fn run(ctx: &Context, project: Option<PathBuf>) -> Result<(), Error> {
+    let manifest_path = ProjectManifest::discover_canonical_path(project.as_deref())?;
+    let manifest = ProjectManifest::load(&manifest_path)?;
+    let state = State::load(&ctx.config)?;
+    let installations = state.installations();
+
+    let mut all_installed = true;
+    let mut products = Vec::new();
+    for product in manifest.products() {
+        let installation_id = product.installation_id();
+        let installation_path = ctx.config.paths.installation_dir.join(&installation_id.0);
+        let installation = installations.get(&installation_id);
+        all_installed &= installation.is_some();
+
+        if ctx.format.is_json() {
+            products.push(json!({
+                "id": installation_id.0,
+                "product": product.name(),
+                "release": product.release(),
+                "installed": installation.is_some(),
+                "path": installation_path,
+                "packages": installation.map(|i| i.packages()),
+                "binaries": installation.map(|i| {
+                    i.binary_proxies()
+                        .iter()
+                        .map(|(name, path)| (name.clone(), json!(installation_path.join(path))))
+                        .collect::<serde_json::Map<_, _>>()
+                }),
+            }));
+            continue;
+        }
+
+        let Some(installation) = installation else {
+            eprintln!(
+                "{} {} ({}) is not installed, install it with `criticalup install`",
+                "error:".bold(),
+                product.name(),
+                product.release()
+            );
+            continue;
+        };
+
+        println!("{}", installation_id.0);
+        println!("  product:   {} ({})", product.name(), product.release());
+        println!("  path:      {}", installation_path.display());
+
+        let mut label = "packages:";
+        for package in installation.packages() {
+            println!("  {label:<10} {package}");
+            label = "";
+        }
+
+        let mut label = "binaries:";
+        for (name, path) in installation.binary_proxies() {
+            println!(
+                "  {label:<10} {name} -> {}",
+                installation_path.join(path).display()
+            );
+            label = "";
+        }
+    }
+
+    ctx.format
+        .document(json!({ "manifest": manifest_path, "products": products }));
+
+    if all_installed {
+        Ok(())
+    } else {
+        Err(Error::Exit(1))
+    }
+}
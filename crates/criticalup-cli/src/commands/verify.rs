@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use criticaltrust::integrity::IntegrityVerifier;
+use criticaltrust::signatures::Keychain;
+use criticalup_core::download_server_client::DownloadServerClient;
+use criticalup_core::lock::FileLock;
+use criticalup_core::offline::OfflineDir;
+use criticalup_core::project_manifest::ProjectManifest;
+use criticalup_core::state::State;
+use owo_colors::OwoColorize;
+use serde_json::json;
+
+use crate::errors::Error;
+use crate::reuse::file_mode;
+use crate::Context;
+
+pub(crate) fn run(
+    ctx: &Context,
+    project: Option<PathBuf>,
+    offline_dir: Option<PathBuf>,
+) -> Result<(), Error> {
+    let state = State::load(&ctx.config)?;
+    let manifest = ProjectManifest::get(project)?;
+    // The keys package manifests are signed with are fetched from the download server, unless
+    // they're read from a directory created by `criticalup mirror`.
+    let client = match offline_dir {
+        Some(dir) => DownloadServerClient::offline(&ctx.config, &state, OfflineDir::new(dir))?,
+        None => DownloadServerClient::new(&ctx.config, &state)?,
+    };
+    let keys = client.get_keys()?;
+
+    // All products are verified before exiting, to report all the problems at once.
+    let mut failed = false;
+    let mut products = Vec::new();
+    for product in manifest.products() {
+        let description = format!("product '{}' ({})", product.name(), product.release());
+        let installation = ctx
+            .config
+            .paths
+            .installation_dir
+            .join(product.installation_id());
+        // Other processes could be changing the installation while it's verified.
+        let _lock = FileLock::installation(&ctx.config, &product.installation_id())?;
+        state.reload()?;
+
+        let result = if installation.is_dir() {
+            let recorded = state
+                .installations()
+                .get(&product.installation_id())
+                .map(|installation| {
+                    installation
+                        .packages()
+                        .into_iter()
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(verify_installation(&installation, &keys, &recorded)?)
+        } else {
+            None
+        };
+        failed |= !matches!(result, Some(Ok(())));
+
+        if ctx.format.is_json() {
+            products.push(json!({
+                "product": product.name(),
+                "release": product.release(),
+                "installation": product.installation_id().0,
+                "installed": result.is_some(),
+                "intact": matches!(result, Some(Ok(()))),
+                "errors": match &result {
+                    Some(Err(errors)) => errors.clone(),
+                    _ => Vec::new(),
+                },
+            }));
+            continue;
+        }
+
+        match result {
+            None => eprintln!("{} {description} is not installed", "error:".bold()),
+            Some(Ok(())) => println!(
+                "{} {description} passed the integrity checks",
+                "info:".bold()
+            ),
+            Some(Err(errors)) => {
+                eprintln!(
+                    "{} {description} failed the integrity checks, the following errors were found:",
+                    "error:".bold()
+                );
+                for error in errors {
+                    eprintln!("  {error}");
+                }
+            }
+        }
+    }
+
+    ctx.format.document(json!({ "products": products }));
+
+    if failed {
+        Err(Error::Exit(1))
+    } else {
+        Ok(())
+    }
+}
+
+/// Verify every file in the installation against the package manifests shipped in it, and check
+/// that all the `recorded` packages are still in it.
+///
+/// Removing both the files and the package manifest of a package leaves an installation that
+/// passes the integrity checks, so the packages are compared with the ones recorded in the state.
+fn verify_installation(
+    installation: &Path,
+    keys: &Keychain,
+    recorded: &BTreeSet<String>,
+) -> Result<Result<(), Vec<String>>, Error> {
+    let mut verifier = IntegrityVerifier::new(keys);
+    add_dir(installation, "", &mut verifier)?;
+    let verified = match verifier.verify() {
+        Ok(verified) => verified,
+        Err(errors) => return Ok(Err(errors.iter().map(|e| e.to_string()).collect())),
+    };
+
+    let verified: BTreeSet<_> = verified.into_iter().map(|p| p.package).collect();
+    let missing: Vec<_> = recorded
+        .difference(&verified)
+        .map(|package| format!("package {package} is missing from the installation"))
+        .collect();
+    if missing.is_empty() {
+        Ok(Ok(()))
+    } else {
+        Ok(Err(missing))
+    }
+}
+
+/// Add all files in `dir` to the verifier, with their path relative to the installation.
+fn add_dir(dir: &Path, relative: &str, verifier: &mut IntegrityVerifier) -> Result<(), Error> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path_on_disk = entry.path();
+        // Paths in package manifests always use forward slashes, regardless of the platform.
+        let path = format!("{relative}{}", entry.file_name().to_string_lossy());
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            add_dir(&path_on_disk, &format!("{path}/"), verifier)?;
+        } else if file_type.is_file() || path_on_disk.is_file() {
+            // Symlinks to files are verified based on the file they point to, like when unpacking.
+            let file = File::open(&path_on_disk)?;
+            verifier.add_reader(Path::new(&path), file_mode(&file)?, file)?;
+        } else {
+            // Anything else (like a symlink to a directory) can't match a package manifest.
+            verifier.add(Path::new(&path), 0, &[]);
+        }
+    }
+
+    Ok(())
+}
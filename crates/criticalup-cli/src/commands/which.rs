@@ -5,6 +5,7 @@ use crate::errors::Error;
 use crate::errors::Error::BinaryNotInstalled;
 use crate::Context;
 use criticalup_core::project_manifest::ProjectManifest;
+use serde_json::json;
 use std::path::PathBuf;
 
 pub(crate) fn run(ctx: &Context, tool: String, project: Option<PathBuf>) -> Result<(), Error> {
@@ -12,16 +13,22 @@ pub(crate) fn run(ctx: &Context, tool: String, project: Option<PathBuf>) -> Resu
 
     let installation_dir = &ctx.config.paths.installation_dir;
 
+    let mut paths = Vec::new();
     for product in manifest.products() {
         let abs_installation_dir_path = installation_dir.join(product.installation_id());
         let tools_bin_path = abs_installation_dir_path.join(format!("bin/{}", tool));
 
         if tools_bin_path.exists() {
-            println!("{}\n", tools_bin_path.display());
+            if !ctx.format.is_json() {
+                println!("{}\n", tools_bin_path.display());
+            }
+            paths.push(tools_bin_path);
         } else {
             return Err(BinaryNotInstalled(tool));
         }
     }
 
+    ctx.format
+        .document(json!({ "binary": tool, "paths": paths }));
     Ok(())
 }
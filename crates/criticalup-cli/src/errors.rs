@@ -34,9 +34,11 @@ pub(crate) enum Error {
     CantReadTokenFromStdin(#[source] std::io::Error),
     #[error("invalid authentication token provided")]
     InvalidAuthenticationToken,
+    #[error("failed to open the log file at {}", .0.display())]
+    CantOpenLogFile(PathBuf, #[source] std::io::Error),
 
     #[error("some files did not pass the integrity checks after the download\n \
-        please clean your installation directory and re-install the project again\n \
+        the installation was aborted, please try installing the project again\n \
         the following errors were found:\n\n{}",
       .0.iter().map(|err| { err.to_string() }).collect::<Vec<_>>().join("\n")
     )]
@@ -52,18 +54,72 @@ pub(crate) enum Error {
     project's criticalup.toml and run 'criticalup install' command again.\n"
     )]
     BinaryNotInstalled(String),
+    #[error(
+        "no checksum was recorded for '{0}' when it was installed, so it can't be verified.\n\n\
+    Please run 'criticalup install --force' to record it.\n"
+    )]
+    BinaryChecksumMissing(String),
+    #[error(
+        "'{binary}' at {} doesn't match the checksum recorded when it was installed.\n\n\
+    The installation might have been modified or corrupted, please run 'criticalup repair' to \
+    restore it.\n",
+        .path.display()
+    )]
+    BinaryModified { binary: String, path: PathBuf },
 
     // This is not *technically* needed, but it provides useful insights when an error happens when
     // invoking a binary proxy. Otherwise people could think the error comes from rustc/cargo/etc.
     #[error("criticalup could not invoke the binary you requested")]
     BinaryProxyInvocationFailed(#[source] Box<Error>),
 
+    #[error("package '{package}' is not part of release {release} of {product}")]
+    PackageNotFoundInRelease {
+        product: String,
+        release: String,
+        package: String,
+    },
+    #[error(
+        "package '{package}' depends on '{dependency}', which is not part of release {release} \
+            of {product}"
+    )]
+    PackageDependencyNotFound {
+        product: String,
+        release: String,
+        package: String,
+        dependency: String,
+    },
+    #[error(
+        "the dependencies of release {release} of {product} contain a cycle: {}",
+        .cycle.join(" -> ")
+    )]
+    PackageDependencyCycle {
+        product: String,
+        release: String,
+        cycle: Vec<String>,
+    },
     #[error(
-        "dependencies are not supported in the current criticalup release.\n \
-            found package {0} with dependencies in the manifest.\n \
-            please updated criticalup to the latest version to resolve this error."
+        "package '{package}' is not available in any archive format supported by this release \
+            of criticalup (available formats: {})\n \
+            please update criticalup to the latest version to resolve this error.",
+        if .available.is_empty() { "none".into() } else { .available.join(", ") }
     )]
-    PackageDependenciesNotSupported(String),
+    NoSupportedArtifactFormat {
+        package: String,
+        available: Vec<String>,
+    },
+
+    #[error("failed to create a staging directory for the installation at {}", path.display())]
+    StagingDirCreationFailed {
+        path: PathBuf,
+        #[source]
+        kind: std::io::Error,
+    },
+    #[error("failed to move the staged installation to {}", path.display())]
+    StagedInstallationPersistFailed {
+        path: PathBuf,
+        #[source]
+        kind: std::io::Error,
+    },
 
     #[error("there was an error while trying to delete the unused installation directory at {}", path.display())]
     DeletingUnusedInstallationDir {
@@ -1,17 +1,27 @@
 // SPDX-FileCopyrightText: The Ferrocene Developers
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+mod archive;
 mod binary_proxies;
 mod commands;
 mod errors;
+mod logger;
+mod output;
+mod progress;
+mod reuse;
 mod spawn;
+mod staging;
 
 use crate::errors::Error;
-use clap::{command, Command, CommandFactory, FromArgMatches, Parser, Subcommand};
+use crate::output::{json_diagnostic, OutputFormat};
+use clap::{ArgAction, Command, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use criticalup_core::cache::ByteSize;
 use criticalup_core::config::Config;
 pub use criticalup_core::config::WhitelabelConfig;
 use std::ffi::OsString;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::time::Instant;
 
 /// Use a custom help template to solve some issues with Clap's default one, namely the
 /// command-subcommand-subsubcomand at the top of each heading.
@@ -19,7 +29,14 @@ use std::path::PathBuf;
 /// The syntax is available in the documentation for [`clap::Command::help_template`].
 const HELP_TEMPLATE: &str = "{about}\n\n{usage-heading}\n{tab}{usage}\n\n{all-args}";
 
-fn main_inner(whitelabel: WhitelabelConfig, args: &[OsString]) -> Result<(), Error> {
+/// Run criticalup, setting `format` to the output format requested by the command line once it's
+/// parsed, so that errors can be printed in it.
+fn main_inner(
+    whitelabel: WhitelabelConfig,
+    args: &[OsString],
+    format: &mut OutputFormat,
+) -> Result<(), Error> {
+    let start = Instant::now();
     let arg0 = binary_proxies::arg0(&whitelabel)?;
     #[cfg(windows)]
     let arg0 = arg0
@@ -28,6 +45,9 @@ fn main_inner(whitelabel: WhitelabelConfig, args: &[OsString]) -> Result<(), Err
         .unwrap_or(arg0);
 
     if arg0 != whitelabel.name {
+        // The arguments belong to the proxied binary, so logging is only configured through the
+        // environment.
+        logger::init(OutputFormat::Text, 0)?;
         return binary_proxies::proxy(whitelabel)
             .map_err(|e| Error::BinaryProxyInvocationFailed(Box::new(e)));
     }
@@ -40,8 +60,19 @@ fn main_inner(whitelabel: WhitelabelConfig, args: &[OsString]) -> Result<(), Err
         .map_err(Error::CliArgumentParsing)?;
     let cli = Cli::from_arg_matches(&matches).map_err(Error::CliArgumentParsing)?;
 
+    // The default output format is configured in the configuration file, so errors loading it
+    // can only be printed in the format requested by the command line.
+    *format = cli.format.unwrap_or(OutputFormat::Text);
     let config = Config::detect(whitelabel)?;
-    let ctx = Context { config };
+    if let (None, Some(configured)) = (cli.format, &config.format) {
+        // The configured format was already validated while loading the configuration.
+        *format = OutputFormat::from_str(configured, false).unwrap_or(*format);
+    }
+    logger::init(*format, cli.verbose as i8 - cli.quiet as i8)?;
+    let ctx = Context {
+        config,
+        format: *format,
+    };
 
     match cli.commands {
         Commands::Auth { commands } => match commands {
@@ -49,21 +80,54 @@ fn main_inner(whitelabel: WhitelabelConfig, args: &[OsString]) -> Result<(), Err
             Some(AuthCommands::Remove) => commands::auth_remove::run(&ctx)?,
             None => commands::auth::run(&ctx)?,
         },
-        Commands::Install { project } => commands::install::run(&ctx, project)?,
+        Commands::Cache { commands } => match commands {
+            CacheCommands::List => commands::cache::list(&ctx)?,
+            CacheCommands::Prune { max_size } => commands::cache::prune(&ctx, max_size)?,
+            CacheCommands::Clear => commands::cache::clear(&ctx)?,
+        },
+        Commands::Config { commands } => match commands {
+            ConfigCommands::Get { key } => commands::config::get(&ctx, key)?,
+            ConfigCommands::Set { key, value } => commands::config::set(&ctx, key, value)?,
+            ConfigCommands::List => commands::config::list(&ctx)?,
+        },
+        Commands::Install {
+            project,
+            jobs,
+            offline: _,
+            from,
+            force,
+        } => commands::install::run(&ctx, project, jobs, from, force)?,
         Commands::Clean => commands::clean::run(&ctx)?,
+        Commands::List => commands::list::run(&ctx)?,
+        Commands::Mirror {
+            product,
+            release,
+            packages,
+            out,
+            jobs,
+        } => commands::mirror::run(&ctx, product, release, packages, out, jobs)?,
         Commands::Remove { project } => commands::remove::run(&ctx, project)?,
+        Commands::Repair { project, jobs } => commands::repair::run(&ctx, project, jobs)?,
         Commands::Run { command, project } => commands::run::run(&ctx, command, project)?,
+        Commands::Show { project } => commands::show::run(&ctx, project)?,
+        Commands::Verify {
+            project,
+            offline: _,
+            from,
+        } => commands::verify::run(&ctx, project, from)?,
         Commands::Which {
             binary: tool,
             project,
         } => commands::which::run(&ctx, tool, project)?,
     }
 
+    log::debug!("finished in {:.1?}", start.elapsed());
     Ok(())
 }
 
 pub fn main(whitelabel: WhitelabelConfig, args: &[OsString]) -> i32 {
-    match main_inner(whitelabel, args) {
+    let mut format = OutputFormat::Text;
+    match main_inner(whitelabel, args, &mut format) {
         Ok(()) => 0,
         Err(Error::Exit(code)) => code,
         Err(Error::CliArgumentParsing(err)) => {
@@ -75,12 +139,21 @@ pub fn main(whitelabel: WhitelabelConfig, args: &[OsString]) -> i32 {
             }
         }
         Err(err) => {
-            eprintln!("error: {err}");
+            let mut causes = Vec::new();
+            let mut source = std::error::Error::source(&err);
+            while let Some(cause) = source {
+                causes.push(cause.to_string());
+                source = cause.source();
+            }
 
-            let mut err = &err as &dyn std::error::Error;
-            while let Some(source) = err.source() {
-                err = source;
-                eprintln!("  caused by: {source}");
+            match format {
+                OutputFormat::Text => {
+                    eprintln!("error: {err}");
+                    for cause in causes {
+                        eprintln!("  caused by: {cause}");
+                    }
+                }
+                OutputFormat::Json => json_diagnostic("error", err, &causes),
             }
 
             1
@@ -100,6 +173,7 @@ fn override_help_template(command: &mut Command) {
 
 struct Context {
     config: Config,
+    format: OutputFormat,
 }
 
 /// CriticalUp is the official tool to download and install Ferrocene.
@@ -109,6 +183,19 @@ struct Context {
 struct Cli {
     #[command(subcommand)]
     commands: Commands,
+
+    /// Format of the output, `json` prints JSON documents or line-delimited JSON events
+    /// [default: $CRITICALUP_FORMAT, `format` setting or text]
+    #[arg(long, global = true, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Log more details, can be repeated [default level: $CRITICALUP_LOG or warn]
+    #[arg(long, short, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Log less, can be repeated
+    #[arg(long, short, global = true, action = ArgAction::Count)]
+    quiet: u8,
 }
 
 #[derive(Debug, Subcommand, Clone)]
@@ -118,16 +205,68 @@ enum Commands {
         #[command(subcommand)]
         commands: Option<AuthCommands>,
     },
+    /// Show and remove the package archives in the download cache
+    Cache {
+        #[command(subcommand)]
+        commands: CacheCommands,
+    },
+    /// Show and change the settings in the criticalup configuration file
+    Config {
+        #[command(subcommand)]
+        commands: ConfigCommands,
+    },
     /// Install the toolchain for the given project based on the manifest `criticalup.toml`
     Install {
         /// Path to the manifest `criticalup.toml`
         #[arg(long)]
         project: Option<PathBuf>,
+
+        /// Number of packages to download in parallel [default: $CRITICALUP_JOBS or 4]
+        #[arg(long, short = 'j')]
+        jobs: Option<NonZeroUsize>,
+
+        /// Install without network access, from the directory passed to `--from`
+        #[arg(long, requires = "from")]
+        offline: bool,
+
+        /// Directory with the keys, release manifests and package archives to install from
+        #[arg(long, requires = "offline", value_name = "DIR")]
+        from: Option<PathBuf>,
+
+        /// Reinstall the toolchain, replacing the existing installation if there is one
+        #[arg(long)]
+        force: bool,
     },
 
     /// Delete all unused and untracked installations
     Clean,
 
+    /// List all installations with the manifests using them
+    List,
+
+    /// Download a release to a directory, to install it with `install --offline` somewhere else
+    Mirror {
+        /// Name of the product to download
+        #[arg(long)]
+        product: String,
+
+        /// Release of the product to download
+        #[arg(long)]
+        release: String,
+
+        /// Package to download, can be repeated [default: all packages]
+        #[arg(long = "package", value_name = "PACKAGE")]
+        packages: Vec<String>,
+
+        /// Directory to download the release to
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+
+        /// Number of packages to download in parallel [default: $CRITICALUP_JOBS or 4]
+        #[arg(long, short = 'j')]
+        jobs: Option<NonZeroUsize>,
+    },
+
     /// Run a command for a given toolchain
     Run {
         /// Command with possible args to run
@@ -146,6 +285,39 @@ enum Commands {
         project: Option<PathBuf>,
     },
 
+    /// Show the installed toolchain for the given project, with its binaries
+    Show {
+        /// Path to the manifest `criticalup.toml`
+        #[arg(long)]
+        project: Option<PathBuf>,
+    },
+
+    /// Verify the installed toolchain and download again the packages that fail the checks
+    Repair {
+        /// Path to the manifest `criticalup.toml`
+        #[arg(long)]
+        project: Option<PathBuf>,
+
+        /// Number of packages to download in parallel [default: $CRITICALUP_JOBS or 4]
+        #[arg(long, short = 'j')]
+        jobs: Option<NonZeroUsize>,
+    },
+
+    /// Check that the installed toolchain was not modified since it was installed
+    Verify {
+        /// Path to the manifest `criticalup.toml`
+        #[arg(long)]
+        project: Option<PathBuf>,
+
+        /// Verify without network access, with the keys in the directory passed to `--from`
+        #[arg(long, requires = "from")]
+        offline: bool,
+
+        /// Directory with the keys, like the ones created by `criticalup mirror`
+        #[arg(long, requires = "offline", value_name = "DIR")]
+        from: Option<PathBuf>,
+    },
+
     /// Display which binary will be run for a given command
     Which {
         /// Name of the binary to find the absolute path of
@@ -156,6 +328,44 @@ enum Commands {
     },
 }
 
+#[derive(Debug, Subcommand, Clone)]
+enum CacheCommands {
+    /// List the package archives in the download cache
+    List,
+    /// Remove the least recently used archives until the cache fits in its maximum size
+    Prune {
+        /// Maximum size of the cache, like `500M` or `10G` [default: $CRITICALUP_CACHE_MAX_SIZE or 10G]
+        #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+        max_size: Option<ByteSize>,
+    },
+    /// Remove all the archives in the download cache
+    Clear,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+enum ConfigCommands {
+    /// Show the value of a setting in the configuration file
+    Get {
+        /// Name of the setting, like `jobs` or `http-proxy`
+        key: String,
+    },
+    /// Change the value of a setting in the configuration file
+    Set {
+        /// Name of the setting, like `jobs` or `http-proxy`
+        key: String,
+        /// New value of the setting, or an empty string to remove it
+        value: String,
+    },
+    /// List the settings configured in the configuration file or in the environment
+    List,
+}
+
+fn parse_byte_size(value: &str) -> Result<ByteSize, String> {
+    value
+        .parse()
+        .map_err(|()| "expected a size like `500M` or `10G`".into())
+}
+
 #[derive(Debug, Subcommand, Clone)]
 enum AuthCommands {
     /// Remove the authentication token used to interact with the download server
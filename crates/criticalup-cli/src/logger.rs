@@ -0,0 +1,178 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Logger printing the diagnostics emitted by criticalup (for example when a request is retried,
+//! or every request sent at higher verbosity) to stderr, in the same style as the other messages
+//! or as JSON. Records can also be appended as JSON lines to the file in `CRITICALUP_LOG_FILE`,
+//! for example to ingest them in CI.
+//!
+//! Only warnings and errors are logged by default. `CRITICALUP_LOG` changes the level (`off`,
+//! `error`, `warn`, `info`, `debug` or `trace`), and each `-v` or `-q` raises or lowers it by one.
+//! The file has its own level, set with `CRITICALUP_LOG_FILE_LEVEL` and defaulting to the one in
+//! `CRITICALUP_LOG`, so that it's not affected by `-v` and `-q`.
+
+use crate::errors::{Error, LibError};
+use crate::output::{json_diagnostic, versioned, OutputFormat};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use owo_colors::OwoColorize;
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LEVEL_ENV_VAR: &str = "CRITICALUP_LOG";
+const FILE_ENV_VAR: &str = "CRITICALUP_LOG_FILE";
+const FILE_LEVEL_ENV_VAR: &str = "CRITICALUP_LOG_FILE_LEVEL";
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+
+/// Levels from the least to the most verbose, to adjust them with `-v` and `-q`.
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Set up the logger, making it more verbose for positive `verbosity` and less for negative ones.
+pub(crate) fn init(format: OutputFormat, verbosity: i8) -> Result<(), Error> {
+    let level = level_from_env(LEVEL_ENV_VAR)?.unwrap_or(DEFAULT_LEVEL);
+    let stderr_level = adjust_level(level, verbosity);
+    let file = match std::env::var_os(FILE_ENV_VAR) {
+        Some(path) if !path.is_empty() => {
+            let path = PathBuf::from(path);
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| Error::CantOpenLogFile(path, e))?;
+            Some(LogFile {
+                file: Mutex::new(file),
+                level: level_from_env(FILE_LEVEL_ENV_VAR)?.unwrap_or(level),
+            })
+        }
+        _ => None,
+    };
+
+    // Records are only passed to the logger up to the level of its most verbose sink.
+    let max_level = file
+        .as_ref()
+        .map_or(stderr_level, |file| file.level.max(stderr_level));
+    let logger = LOGGER.get_or_init(|| Logger {
+        format,
+        level: stderr_level,
+        file,
+    });
+    // Setting the logger fails only if it was already set, in which case there is nothing to do.
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+    Ok(())
+}
+
+fn level_from_env(name: &'static str) -> Result<Option<LevelFilter>, Error> {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => match value.parse() {
+            Ok(level) => Ok(Some(level)),
+            Err(_) => Err(LibError::InvalidEnvironmentVariable { name, value }.into()),
+        },
+        _ => Ok(None),
+    }
+}
+
+fn adjust_level(level: LevelFilter, verbosity: i8) -> LevelFilter {
+    let current = LEVELS.iter().position(|l| *l == level).unwrap_or(0) as isize;
+    let adjusted = (current + verbosity as isize).clamp(0, LEVELS.len() as isize - 1);
+    LEVELS[adjusted as usize]
+}
+
+struct Logger {
+    format: OutputFormat,
+    /// Level of the records printed to stderr.
+    level: LevelFilter,
+    file: Option<LogFile>,
+}
+
+struct LogFile {
+    file: Mutex<File>,
+    level: LevelFilter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        accepts(self.level, metadata)
+            || self
+                .file
+                .as_ref()
+                .is_some_and(|file| accepts(file.level, metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        let level = record.level().as_str().to_ascii_lowercase();
+        if accepts(self.level, record.metadata()) {
+            match self.format {
+                OutputFormat::Text => {
+                    eprintln!("{} {}", format!("{level}:").bold(), record.args())
+                }
+                OutputFormat::Json => json_diagnostic(&level, record.args(), &[]),
+            }
+        }
+
+        let file = self.file.as_ref();
+        if let Some(file) = file.filter(|file| accepts(file.level, record.metadata())) {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs_f64())
+                .unwrap_or(0.0);
+            let line = versioned(
+                json!({}),
+                json!({
+                    "timestamp": timestamp,
+                    "level": level,
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                }),
+            );
+            // Logging can't fail, so the line is dropped if it can't be written.
+            let mut file = file
+                .file
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let _ = writeln!(file, "{line}");
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Whether a sink logging up to `level` accepts records with `metadata`.
+fn accepts(level: LevelFilter, metadata: &Metadata) -> bool {
+    // The dependencies' own logs are mostly noise, so only their warnings are shown unless
+    // everything is traced.
+    let ours = ["criticalup", "criticaltrust"]
+        .iter()
+        .any(|prefix| metadata.target().starts_with(prefix));
+    metadata.level() <= level
+        && (ours || metadata.level() <= Level::Warn || level == LevelFilter::Trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adjust_level() {
+        assert_eq!(LevelFilter::Warn, adjust_level(LevelFilter::Warn, 0));
+        assert_eq!(LevelFilter::Info, adjust_level(LevelFilter::Warn, 1));
+        assert_eq!(LevelFilter::Trace, adjust_level(LevelFilter::Warn, 3));
+        assert_eq!(LevelFilter::Trace, adjust_level(LevelFilter::Warn, 10));
+        assert_eq!(LevelFilter::Error, adjust_level(LevelFilter::Warn, -1));
+        assert_eq!(LevelFilter::Off, adjust_level(LevelFilter::Warn, -5));
+        assert_eq!(LevelFilter::Info, adjust_level(LevelFilter::Debug, -1));
+    }
+}
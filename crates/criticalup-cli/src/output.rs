@@ -0,0 +1,98 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Output of the commands, either as human readable text or as JSON for automation.
+//!
+//! With `--format json` commands print a single JSON document on stdout once they complete, while
+//! long running operations (like `install`) print one JSON event per line (NDJSON) as they happen.
+//! Warnings and errors are printed on stderr as one JSON object per line. Every document, event,
+//! warning and error carries the [`JSON_OUTPUT_VERSION`].
+
+use owo_colors::OwoColorize;
+use serde_json::{json, Value};
+use std::fmt::Display;
+
+/// Version of the JSON output, increased whenever fields are removed or their meaning changes.
+/// Adding new fields or events doesn't change the version, so consumers should ignore them.
+pub(crate) const JSON_OUTPUT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    pub(crate) fn is_json(self) -> bool {
+        self == OutputFormat::Json
+    }
+
+    /// Print an event of a long running command: an `info:` line with `message` as text, or a
+    /// JSON event named `event` with `fields` in it.
+    pub(crate) fn event(self, event: &str, fields: Value, message: impl Display) {
+        match self {
+            OutputFormat::Text => println!("{} {message}", "info:".bold()),
+            OutputFormat::Json => self.json_event(event, fields),
+        }
+    }
+
+    /// Print a JSON event named `event` with `fields` in it, for events without an equivalent in
+    /// the text output.
+    pub(crate) fn json_event(self, event: &str, fields: Value) {
+        if self == OutputFormat::Json {
+            println!("{}", versioned(json!({ "event": event }), fields));
+        }
+    }
+
+    /// Print an informational message that has no equivalent in the JSON output, like the lack of
+    /// anything to do.
+    pub(crate) fn info(self, message: impl Display) {
+        if self == OutputFormat::Text {
+            println!("{} {message}", "info:".bold());
+        }
+    }
+
+    /// Print the JSON document with the result of a command. Commands print their own text
+    /// output, so nothing is printed as text.
+    pub(crate) fn document(self, document: Value) {
+        if self == OutputFormat::Json {
+            println!("{:#}", versioned(json!({}), document));
+        }
+    }
+}
+
+/// Print a warning or an error as JSON on stderr, with the chain of errors that caused it.
+pub(crate) fn json_diagnostic(level: &str, message: impl Display, causes: &[String]) {
+    let diagnostic = json!({
+        "level": level,
+        "message": message.to_string(),
+        "causes": causes,
+    });
+    eprintln!("{}", versioned(json!({}), diagnostic));
+}
+
+/// Merge `fields` into `base`, adding the version of the JSON output.
+pub(crate) fn versioned(mut base: Value, fields: Value) -> Value {
+    base["version"] = JSON_OUTPUT_VERSION.into();
+    if let (Some(base), Value::Object(fields)) = (base.as_object_mut(), fields) {
+        base.extend(fields);
+    }
+    base
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioned() {
+        assert_eq!(
+            json!({ "version": JSON_OUTPUT_VERSION, "event": "sample", "package": "rustc" }),
+            versioned(json!({ "event": "sample" }), json!({ "package": "rustc" }))
+        );
+        assert_eq!(
+            json!({ "version": JSON_OUTPUT_VERSION }),
+            versioned(json!({}), json!({}))
+        );
+    }
+}
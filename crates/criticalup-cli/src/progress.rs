@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Progress of installations, shown as progress bars when stdout is a terminal.
+//!
+//! When stdout is not a terminal (for example in CI logs) or the output is JSON only the
+//! line-based messages are printed, as progress bars would only clutter the output.
+
+use criticalup_core::progress::{ProgressReporter, ProgressStep};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::output::OutputFormat;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+
+pub(crate) struct InstallProgress {
+    format: OutputFormat,
+    bars: Option<Bars>,
+}
+
+struct Bars {
+    multi: MultiProgress,
+    active: Mutex<HashMap<(ProgressStep, String), ProgressBar>>,
+}
+
+impl InstallProgress {
+    pub(crate) fn new(format: OutputFormat) -> Self {
+        if format == OutputFormat::Text && atty::is(atty::Stream::Stdout) {
+            Self::with_draw_target(ProgressDrawTarget::stdout())
+        } else {
+            InstallProgress { format, bars: None }
+        }
+    }
+
+    fn with_draw_target(target: ProgressDrawTarget) -> Self {
+        InstallProgress {
+            format: OutputFormat::Text,
+            bars: Some(Bars {
+                multi: MultiProgress::with_draw_target(target),
+                active: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Print an event (see [`OutputFormat::event`]), above the progress bars if any is shown.
+    pub(crate) fn event(&self, event: &str, fields: Value, message: impl Display) {
+        match &self.bars {
+            Some(bars) => bars
+                .multi
+                .suspend(|| self.format.event(event, fields, message)),
+            None => self.format.event(event, fields, message),
+        }
+    }
+}
+
+impl ProgressReporter for InstallProgress {
+    fn start(&self, step: ProgressStep, package: &str, total: u64) {
+        let Some(bars) = &self.bars else { return };
+        let mut active = bars.lock();
+        let bar = active.entry((step, package.into())).or_insert_with(|| {
+            let verb = match step {
+                ProgressStep::Download => "downloading",
+                ProgressStep::Unpack => "installing",
+                _ => "processing",
+            };
+            bars.multi.add(
+                ProgressBar::new(total)
+                    .with_style(bar_style())
+                    .with_prefix(format!("{verb} {package}")),
+            )
+        });
+        // Steps can be restarted, for example when a download is retried.
+        bar.set_length(total);
+        bar.set_position(0);
+    }
+
+    fn advance(&self, step: ProgressStep, package: &str, bytes: u64) {
+        let Some(bars) = &self.bars else { return };
+        if let Some(bar) = bars.lock().get(&(step, package.into())) {
+            bar.inc(bytes);
+        }
+    }
+
+    fn finish(&self, step: ProgressStep, package: &str) {
+        let Some(bars) = &self.bars else { return };
+        if let Some(bar) = bars.lock().remove(&(step, package.into())) {
+            bar.finish_and_clear();
+            bars.multi.remove(&bar);
+        }
+    }
+}
+
+impl Drop for InstallProgress {
+    fn drop(&mut self) {
+        // Bars of steps that failed are never finished, remove them before the error is shown.
+        if let Some(bars) = &self.bars {
+            let _ = bars.multi.clear();
+        }
+    }
+}
+
+impl Bars {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(ProgressStep, String), ProgressBar>> {
+        // Bars can't be left in an inconsistent state, so poisoning can be ignored.
+        self.active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn bar_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{prefix:30!} [{bar:30}] {bytes:>10}/{total_bytes:<10} {bytes_per_sec:>12} {eta:>4}",
+    )
+    .expect("invalid progress bar template")
+    .progress_chars("=> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_progress_bars() {
+        let progress = InstallProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let bars = progress.bars.as_ref().unwrap();
+        let position = |step, package: &str| {
+            bars.lock()
+                .get(&(step, package.to_string()))
+                .map(|bar| (bar.position(), bar.length().unwrap()))
+        };
+
+        progress.start(ProgressStep::Download, "rustc", 100);
+        progress.start(ProgressStep::Download, "cargo", 50);
+        progress.advance(ProgressStep::Download, "rustc", 60);
+        progress.advance(ProgressStep::Download, "cargo", 10);
+        assert_eq!(Some((60, 100)), position(ProgressStep::Download, "rustc"));
+        assert_eq!(Some((10, 50)), position(ProgressStep::Download, "cargo"));
+
+        // Restarting resets the progress.
+        progress.start(ProgressStep::Download, "rustc", 100);
+        assert_eq!(Some((0, 100)), position(ProgressStep::Download, "rustc"));
+
+        progress.finish(ProgressStep::Download, "rustc");
+        assert_eq!(None, position(ProgressStep::Download, "rustc"));
+        progress.start(ProgressStep::Unpack, "rustc", 20);
+        assert_eq!(Some((0, 20)), position(ProgressStep::Unpack, "rustc"));
+
+        // Updates for unknown bars are ignored.
+        progress.advance(ProgressStep::Unpack, "clippy", 10);
+        progress.finish(ProgressStep::Unpack, "clippy");
+    }
+}
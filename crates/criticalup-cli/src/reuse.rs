@@ -0,0 +1,145 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Reuse of packages that are already installed.
+//!
+//! Changing the packages of a product in `criticalup.toml` changes its installation ID, creating a
+//! new installation. Packages the new installation has in common with existing ones are hard
+//! linked (or copied, when hard links are not supported) from them instead of being downloaded and
+//! unpacked again. Repairing an installation similarly reuses its packages that are still intact.
+//! Reused files are verified like downloaded ones.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use criticaltrust::integrity::IntegrityVerifier;
+use criticaltrust::manifests::{Package, PackageManifest, Release};
+use criticaltrust::signatures::Keychain;
+use criticalup_core::config::Config;
+use criticalup_core::state::State;
+
+use crate::errors::Error;
+
+/// Package of an existing installation that can be reused.
+pub(crate) struct InstalledPackage {
+    installation_dir: PathBuf,
+    manifest_path: PathBuf,
+    package: Package,
+}
+
+/// Find an existing installation containing `package`, built from the same commit as `release`.
+///
+/// Only intact packages are returned: files of other installations could have been modified, and
+/// a package failing the integrity checks once linked would fail the whole installation rather
+/// than being downloaded again.
+pub(crate) fn find_installed_package(
+    config: &Config,
+    state: &State,
+    keys: &Keychain,
+    release: &Release,
+    package: &str,
+) -> Option<InstalledPackage> {
+    state.installations().keys().find_map(|id| {
+        let installation_dir = config.paths.installation_dir.join(&id.0);
+        installed_package_in(&installation_dir, keys, release, package)
+            .filter(|installed| is_intact(installed, keys))
+    })
+}
+
+/// Load `package` from the installation in `installation_dir`, if it's built from the same commit
+/// as `release`.
+///
+/// Package manifests are signed and include the commit they were built from, so a package with the
+/// same name and commit is the same package. Packages missing any of their files are ignored, as
+/// they can't be reused.
+pub(crate) fn installed_package_in(
+    installation_dir: &Path,
+    keys: &Keychain,
+    release: &Release,
+    package: &str,
+) -> Option<InstalledPackage> {
+    let manifest_path = Path::new("share/criticaltrust")
+        .join(&release.product)
+        .join(format!("{package}.json"));
+    let contents = std::fs::read(installation_dir.join(&manifest_path)).ok()?;
+    let manifest: PackageManifest = serde_json::from_slice(&contents).ok()?;
+    let installed = manifest.signed.into_verified(keys).ok()?;
+
+    let matches = installed.product == release.product
+        && installed.package == package
+        && installed.commit == release.commit;
+    let complete = installed
+        .files
+        .iter()
+        .all(|file| installation_dir.join(&file.path).is_file());
+    (matches && complete).then(|| InstalledPackage {
+        installation_dir: installation_dir.into(),
+        manifest_path,
+        package: installed,
+    })
+}
+
+/// Check whether all the files of an installed package still match its package manifest.
+pub(crate) fn is_intact(installed: &InstalledPackage, keys: &Keychain) -> bool {
+    let mut verifier = IntegrityVerifier::new(keys);
+    for path in installed.paths() {
+        let added = File::open(installed.installation_dir.join(path)).and_then(|file| {
+            let mode = file_mode(&file)?;
+            verifier.add_reader(path, mode, file)
+        });
+        if added.is_err() {
+            return false;
+        }
+    }
+    verifier.verify().is_ok()
+}
+
+/// Link all the files of an installed package into `dest`, adding every file to the integrity
+/// verifier with its path relative to `dest`, like [`unpack_and_verify`] does for archives.
+///
+/// [`unpack_and_verify`]: crate::archive::unpack_and_verify
+pub(crate) fn link_and_verify(
+    installed: &InstalledPackage,
+    dest: &Path,
+    verifier: &mut IntegrityVerifier,
+) -> Result<(), Error> {
+    for path in installed.paths() {
+        let source = installed.installation_dir.join(path);
+        let path_on_disk = dest.join(path);
+        if let Some(parent) = path_on_disk.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if std::fs::hard_link(&source, &path_on_disk).is_err() {
+            std::fs::copy(&source, &path_on_disk)?;
+        }
+
+        let file = File::open(&path_on_disk)?;
+        verifier.add_reader(path, file_mode(&file)?, file)?;
+    }
+
+    Ok(())
+}
+
+impl InstalledPackage {
+    /// Paths of all the files of the package, including its package manifest.
+    fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.package
+            .files
+            .iter()
+            .map(|file| Path::new(&file.path))
+            .chain(std::iter::once(self.manifest_path.as_path()))
+    }
+}
+
+/// Mode of a file, as recorded in package manifests.
+#[cfg(unix)]
+pub(crate) fn file_mode(file: &File) -> std::io::Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(file.metadata()?.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_mode(_file: &File) -> std::io::Result<u32> {
+    // File modes are not verified on Windows.
+    Ok(0)
+}
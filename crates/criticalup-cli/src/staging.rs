@@ -0,0 +1,186 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Staging directories, where installations are built before being moved into place.
+//!
+//! An installation is only ever visible at its final path once all of its packages have been
+//! downloaded and verified. Staging directories are removed when they're dropped without being
+//! persisted (for example when an error occurs), and when the user interrupts criticalup with
+//! Ctrl-C while a staging directory exists.
+
+use crate::errors::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+use tempfile::TempDir;
+
+/// Paths of all the staging directories currently in use, removed by the Ctrl-C handler.
+static ACTIVE_STAGING_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Exit code used when interrupted, matching the one of processes killed by SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+pub(crate) struct StagingDir {
+    dir: TempDir,
+}
+
+impl StagingDir {
+    /// Create a new staging directory for `destination`, as a sibling of it. Keeping it in the
+    /// same parent directory ensures it can later be renamed into place atomically.
+    pub(crate) fn new(destination: &Path) -> Result<Self, Error> {
+        install_ctrlc_handler();
+
+        let parent = destination.parent().expect("destination has no parent");
+        let name = destination
+            .file_name()
+            .expect("destination has no file name")
+            .to_string_lossy();
+
+        let err = |kind| Error::StagingDirCreationFailed {
+            path: destination.into(),
+            kind,
+        };
+        std::fs::create_dir_all(parent).map_err(err)?;
+        let dir = tempfile::Builder::new()
+            .prefix(&format!(".{name}-staging-"))
+            .tempdir_in(parent)
+            .map_err(err)?;
+
+        lock_active().push(dir.path().into());
+        Ok(StagingDir { dir })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Move the contents of the staging directory to `destination`, replacing the directory
+    /// already present there (like a previous installation or leftovers of one).
+    ///
+    /// The previous directory is moved aside rather than removed before the staging directory is
+    /// moved into place, so `destination` either has its previous or its new contents, and the
+    /// previous contents are restored if the staging directory can't be moved.
+    pub(crate) fn persist(self, destination: &Path) -> Result<(), Error> {
+        let err = |kind| Error::StagedInstallationPersistFailed {
+            path: destination.into(),
+            kind,
+        };
+
+        // Hold the lock while moving the directories, so that the Ctrl-C handler either removes
+        // the staging directory before it's moved, or doesn't see it at all.
+        let mut active = lock_active();
+        let previous = if destination.exists() {
+            let parent = self.dir.path().parent().expect("staging dir has no parent");
+            let name = destination
+                .file_name()
+                .expect("destination has no file name")
+                .to_string_lossy();
+            let aside = tempfile::Builder::new()
+                .prefix(&format!(".{name}-previous-"))
+                .tempdir_in(parent)
+                .map_err(err)?;
+            let path = aside.path().join("installation");
+            std::fs::rename(destination, &path).map_err(err)?;
+            Some((aside, path))
+        } else {
+            None
+        };
+
+        if let Err(e) = std::fs::rename(self.dir.path(), destination) {
+            if let Some((_, path)) = &previous {
+                let _ = std::fs::rename(path, destination);
+            }
+            return Err(err(e));
+        }
+        active.retain(|path| path != self.dir.path());
+        // Dropping the `TempDir` the previous contents were moved to removes them.
+        drop(previous);
+
+        // Removing the (now missing) staging directory when `TempDir` is dropped is a no-op.
+        Ok(())
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        // The directory itself is removed by `TempDir`.
+        lock_active().retain(|path| path != self.dir.path());
+    }
+}
+
+fn lock_active() -> std::sync::MutexGuard<'static, Vec<PathBuf>> {
+    // The list of paths can't be left in an inconsistent state, so poisoning can be ignored.
+    ACTIVE_STAGING_DIRS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn install_ctrlc_handler() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        // Failing to install the handler only means staging directories are not cleaned up on
+        // Ctrl-C, which is not worth aborting the installation for.
+        let _ = ctrlc::set_handler(|| {
+            for path in lock_active().drain(..) {
+                let _ = std::fs::remove_dir_all(path);
+            }
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_staging_dir_persist() {
+        let root = TempDir::new().unwrap();
+        let destination = root.path().join("toolchains").join("abcd");
+
+        let staging = StagingDir::new(&destination).unwrap();
+        let staging_path = staging.path().to_path_buf();
+        assert_eq!(Some(destination.parent().unwrap()), staging_path.parent());
+        std::fs::write(staging_path.join("file"), b"hello").unwrap();
+        assert!(lock_active().contains(&staging_path));
+
+        staging.persist(&destination).unwrap();
+        assert_eq!(
+            b"hello",
+            std::fs::read(destination.join("file")).unwrap().as_slice()
+        );
+        assert!(!staging_path.exists());
+        assert!(!lock_active().contains(&staging_path));
+    }
+
+    #[test]
+    fn test_staging_dir_persist_replaces_leftovers() {
+        let root = TempDir::new().unwrap();
+        let destination = root.path().join("abcd");
+        std::fs::create_dir_all(&destination).unwrap();
+        std::fs::write(destination.join("leftover"), b"").unwrap();
+
+        let staging = StagingDir::new(&destination).unwrap();
+        std::fs::write(staging.path().join("file"), b"").unwrap();
+        staging.persist(&destination).unwrap();
+
+        assert!(destination.join("file").exists());
+        assert!(!destination.join("leftover").exists());
+        // The previous contents are removed once they're replaced.
+        assert_eq!(1, std::fs::read_dir(root.path()).unwrap().count());
+    }
+
+    #[test]
+    fn test_staging_dir_removed_on_drop() {
+        let root = TempDir::new().unwrap();
+        let destination = root.path().join("abcd");
+
+        let staging = StagingDir::new(&destination).unwrap();
+        let staging_path = staging.path().to_path_buf();
+        std::fs::write(staging_path.join("file"), b"").unwrap();
+        drop(staging);
+
+        assert!(!staging_path.exists());
+        assert!(!destination.exists());
+        assert!(!lock_active().contains(&staging_path));
+    }
+}
@@ -55,3 +55,20 @@ fn set_token(test_env: &TestEnvironment, token: &str) {
         .status
         .success());
 }
+
+#[test]
+fn token_as_json() {
+    let test_env = TestEnvironment::prepare();
+    set_token(&test_env, MOCK_AUTH_TOKENS[1].0);
+
+    assert_output!(test_env.cmd().args(["auth", "--format", "json"]));
+}
+
+#[test]
+fn invalid_token_as_json() {
+    let test_env = TestEnvironment::prepare();
+    set_token(&test_env, MOCK_AUTH_TOKENS[2].0);
+    test_env.revoke_token(MOCK_AUTH_TOKENS[2].0);
+
+    assert_output!(test_env.cmd().args(["auth", "--format", "json"]));
+}
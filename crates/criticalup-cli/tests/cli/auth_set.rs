@@ -27,6 +27,10 @@ fn byte_zero_via_stdin() {
     assert_eq!(0, test_env.requests_served_by_mock_download_server());
 }
 
+fn local_port_regex() -> Regex {
+    Regex::new(r"127.0.0.1:\d+").expect("regex creation failed.")
+}
+
 // This is a macro instead of a function because otherwise insta detects the name of the helper
 // function as the name of the test.
 macro_rules! run_cmd {
@@ -39,7 +43,7 @@ macro_rules! run_cmd {
                 // this regex replacement dance is required because this nested macro tests
                 // set is instantiating the test server twice which means each run gives
                 // a different local port. we replace with a stable port just for this test.
-                let re = Regex::new(r"127.0.0.1:\d+").expect("regex creation failed.");
+                let re = local_port_regex();
                 let left_str = String::from_utf8(out.stderr.clone())
                     .expect("string creation from bytes failed.");
                 let right_str = String::from_utf8(expected.stderr.clone())
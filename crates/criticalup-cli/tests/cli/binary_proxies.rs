@@ -3,6 +3,7 @@
 
 use crate::assert_output;
 use crate::utils::TestEnvironment;
+use sha2::{Digest, Sha256};
 use std::io::Write;
 use std::path::Path;
 use std::process::{Command, Stdio};
@@ -86,6 +87,131 @@ fn invoking_inside_of_installed_project() {
         .current_dir(current_dir.path()));
 }
 
+#[test]
+fn invoking_verified_binary() {
+    let test_env = TestEnvironment::prepare();
+
+    let current_dir = tempdir().unwrap();
+    std::fs::write(
+        current_dir.path().join("criticalup.toml"),
+        PROJECT_MANIFEST.as_bytes(),
+    )
+    .unwrap();
+
+    let binary = test_env
+        .root()
+        .join("toolchains")
+        .join(INSTALLATION_ID)
+        .join("bin")
+        .join("sample");
+    compile_to(&binary, r#"fn main() { println!("proxies work!"); }"#);
+    write_state_with_checksums(&test_env, &[("sample", &binary)]);
+
+    // Checks can be enabled both through the environment and the project manifest.
+    assert_output!(test_env
+        .binary_proxy("sample")
+        .env("CRITICALUP_VERIFY_BINARIES", "true")
+        .current_dir(current_dir.path()));
+
+    let manifest = PROJECT_MANIFEST.replace(
+        "manifest-version = 1",
+        "manifest-version = 1\nverify-binaries = true",
+    );
+    std::fs::write(current_dir.path().join("criticalup.toml"), manifest).unwrap();
+    assert_output!(test_env
+        .binary_proxy("sample")
+        .current_dir(current_dir.path()));
+}
+
+#[test]
+fn invoking_modified_binary() {
+    let test_env = TestEnvironment::prepare();
+
+    let current_dir = tempdir().unwrap();
+    std::fs::write(
+        current_dir.path().join("criticalup.toml"),
+        PROJECT_MANIFEST.as_bytes(),
+    )
+    .unwrap();
+
+    let binary = test_env
+        .root()
+        .join("toolchains")
+        .join(INSTALLATION_ID)
+        .join("bin")
+        .join("sample");
+    compile_to(&binary, r#"fn main() { println!("proxies work!"); }"#);
+    write_state_with_checksums(&test_env, &[("sample", &binary)]);
+    compile_to(&binary, r#"fn main() { println!("modified!"); }"#);
+
+    // Without checks the modified binary is executed.
+    assert_output!(test_env
+        .binary_proxy("sample")
+        .current_dir(current_dir.path()));
+
+    assert_output!(test_env
+        .binary_proxy("sample")
+        .env("CRITICALUP_VERIFY_BINARIES", "true")
+        .current_dir(current_dir.path()));
+}
+
+#[test]
+fn invoking_binary_without_checksum() {
+    let test_env = TestEnvironment::prepare();
+
+    let current_dir = tempdir().unwrap();
+    std::fs::write(
+        current_dir.path().join("criticalup.toml"),
+        PROJECT_MANIFEST.as_bytes(),
+    )
+    .unwrap();
+
+    let binary = test_env
+        .root()
+        .join("toolchains")
+        .join(INSTALLATION_ID)
+        .join("bin")
+        .join("sample");
+    compile_to(&binary, r#"fn main() { println!("proxies work!"); }"#);
+    write_state_with_checksums(&test_env, &[]);
+
+    assert_output!(test_env
+        .binary_proxy("sample")
+        .env("CRITICALUP_VERIFY_BINARIES", "true")
+        .current_dir(current_dir.path()));
+}
+
+/// Create a state file referencing the `sample` binary proxy, recording the checksums of the
+/// provided binaries like installations do.
+fn write_state_with_checksums(test_env: &TestEnvironment, binaries: &[(&str, &Path)]) {
+    let checksums: serde_json::Map<_, _> = binaries
+        .iter()
+        .map(|(name, path)| {
+            let digest = Sha256::digest(std::fs::read(path).unwrap());
+            let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+            (name.to_string(), hex.into())
+        })
+        .collect();
+    std::fs::write(
+        test_env.root().join("state.json"),
+        serde_json::json!({
+            "version": 1,
+            "installations": {
+                INSTALLATION_ID: {
+                    "manifests": ["/path/to/manifest/a"],
+                    "binary_proxies": {
+                        "sample": "bin/sample",
+                    },
+                    "binary_proxy_digests": checksums,
+                },
+            },
+        })
+        .to_string()
+        .as_bytes(),
+    )
+    .unwrap();
+}
+
 pub(crate) fn compile_to(dest: &Path, source: &str) {
     if let Some(parent) = dest.parent() {
         std::fs::create_dir_all(parent).unwrap();
@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::assert_output;
+use crate::utils::TestEnvironment;
+
+#[test]
+fn help_message() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["cache", "--help"]));
+}
+
+fn set_cache_max_size_to_zero(test_env: &TestEnvironment) {
+    let output = test_env
+        .cmd()
+        .args(["config", "set", "cache-max-size", "0"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+}
+
+/// Archives are signed with keys generated by each test, so their checksums and sizes change
+/// from one run to the next. Archives downloaded concurrently can also be listed in any order,
+/// so their formats are hidden too.
+macro_rules! assert_cache_output {
+    ($out:expr) => {{
+        let mut settings = insta::Settings::clone_current();
+        settings.add_filter(r"[0-9a-f]{64}", "[sha256]");
+        settings.add_filter(r"  tar\.(xz|zst) ", "  [format] ");
+        settings.add_filter(r" +\d+(\.\d)? (B|KiB)", " [size]");
+        settings.bind(|| assert_output!($out));
+    }};
+}
+
+#[test]
+fn cache_is_empty_by_default() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["cache", "list"]));
+}
+
+#[test]
+fn installations_share_cached_archives() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+
+    let requests = test_env.requests_served_by_mock_download_server();
+    test_env.install_project("first", &["rustc", "cargo"]);
+    let first_requests = test_env.requests_served_by_mock_download_server() - requests;
+
+    // The archive of rustc is already in the cache, so only the manifests are downloaded.
+    let requests = test_env.requests_served_by_mock_download_server();
+    test_env.install_project("second", &["rustc"]);
+    let second_requests = test_env.requests_served_by_mock_download_server() - requests;
+    assert_eq!(first_requests - 2, second_requests);
+
+    assert_cache_output!(test_env.cmd().args(["cache", "list"]));
+}
+
+#[test]
+fn cache_prune() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    test_env.install_project("proj", &["rustc", "cargo"]);
+
+    // The cache is already smaller than the default maximum size.
+    assert_cache_output!(test_env.cmd().args(["cache", "prune"]));
+    assert_cache_output!(test_env.cmd().args(["cache", "prune", "--max-size", "0"]));
+    assert_output!(test_env.cmd().args(["cache", "list"]));
+}
+
+#[test]
+fn cache_prune_with_invalid_size() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["cache", "prune", "--max-size", "10X"]));
+}
+
+#[test]
+fn cache_clear() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    test_env.install_project("proj", &["rustc", "cargo"]);
+
+    assert_cache_output!(test_env.cmd().args(["cache", "clear"]));
+    assert_output!(test_env.cmd().args(["cache", "list"]));
+}
+
+#[test]
+fn install_respects_cache_max_size() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    set_cache_max_size_to_zero(&test_env);
+    test_env.install_project("proj", &["rustc"]);
+    assert_output!(test_env.cmd().args(["cache", "list"]));
+}
+
+#[test]
+fn cache_is_not_pruned_while_in_use() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    test_env.install_project("first", &["rustc"]);
+
+    // Simulate another process installing from the cache.
+    let lock_path = test_env.root().join("locks").join("cache.lock");
+    let lock = std::fs::File::create(&lock_path).unwrap();
+    lock.lock_shared().unwrap();
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(
+        &regex::escape(&test_env.root().display().to_string()),
+        "[root]",
+    );
+    settings.bind(|| {
+        assert_output!(test_env
+            .cmd()
+            .env("CRITICALUP_LOCK_TIMEOUT", "1")
+            .args(["cache", "clear"]));
+    });
+
+    // Installations can still use the cache, but they leave pruning it to the next one.
+    set_cache_max_size_to_zero(&test_env);
+    test_env.install_project("second", &["cargo"]);
+
+    drop(lock);
+    assert_cache_output!(test_env.cmd().args(["cache", "list"]));
+}
+
+#[test]
+fn cache_list_as_json() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    test_env.install_project("first", &["rustc"]);
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(r"[0-9a-f]{64}", "[sha256]");
+    settings.add_filter(r#""size": \d+"#, r#""size": [size]"#);
+    settings.bind(|| assert_output!(test_env.cmd().args(["cache", "list", "--format", "json"])));
+}
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::assert_output;
-use crate::utils::TestEnvironment;
+use crate::utils::{auth_set_with_valid_token, TestEnvironment, TestPackage};
 use serde_json::{json, Value};
 use std::fs;
 use std::fs::File;
@@ -255,3 +255,59 @@ fn removes_unused_installations_from_disk_that_do_not_have_state() {
     assert!(!toolchains_dir.join(installation_id_2).exists()); // Does not exist.
     assert!(!toolchains_dir.join(installation_id_3).exists()); // Does not exist.
 }
+
+#[test]
+fn remove_and_clean_as_json() {
+    let env = TestEnvironment::prepare();
+    env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc").binary("bin/rustc", b"rustc binary")],
+    );
+    auth_set_with_valid_token(&env);
+
+    let manifest = env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    let output = env
+        .cmd()
+        .args(["install", "--project"])
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // `remove` keeps the installation in the state without manifests, for `clean` to remove it.
+    let untracked = env.root().join("toolchains").join("untracked");
+    fs::create_dir_all(&untracked).unwrap();
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(&regex::escape(&env.root().display().to_string()), "[root]");
+    settings.bind(|| {
+        assert_output!(env
+            .cmd()
+            .args(["remove", "--format", "json", "--project"])
+            .arg(&manifest));
+        assert_output!(env.cmd().args(["clean", "--format", "json"]));
+    });
+}
+
+#[test]
+fn removes_stale_staging_dirs_of_unlocked_installations() {
+    let test_env = TestEnvironment::prepare();
+    let toolchains = test_env.root().join("toolchains");
+    let stale = [".stale-staging-a1b2c3", ".stale-previous-d4e5f6"];
+    let in_use = ".in-use-staging-a1b2c3";
+    for dir in stale.iter().chain([&in_use]) {
+        fs::create_dir_all(toolchains.join(dir).join("bin")).unwrap();
+    }
+
+    // Another process is installing `in-use`, so its staging directory must be kept.
+    fs::create_dir_all(test_env.root().join("locks")).unwrap();
+    let lock = File::create(test_env.root().join("locks/installation-in-use.lock")).unwrap();
+    lock.lock().unwrap();
+
+    let output = test_env.cmd().arg("clean").output().unwrap();
+    assert!(output.status.success());
+    for dir in stale {
+        assert!(!toolchains.join(dir).exists(), "{dir} was not removed");
+    }
+    assert!(toolchains.join(in_use).exists());
+}
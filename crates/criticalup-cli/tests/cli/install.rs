@@ -2,7 +2,12 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::assert_output;
-use crate::utils::{auth_set_with_valid_token, construct_toolchains_product_path, TestEnvironment};
+use crate::utils::{
+    auth_set_with_valid_token, construct_toolchains_product_path, TestEnvironment, TestPackage,
+    DEFAULT_PRODUCT, DEFAULT_RELEASE,
+};
+use criticaltrust::manifests::ReleaseArtifactFormat;
+use mock_download_server::TransientError;
 use serde_json::json;
 use std::io::Write;
 
@@ -67,6 +72,646 @@ fn already_installed_toolchain_should_not_throw_error() {
     assert_output!(test_env.cmd().args(["install", "--project", manifest_path]))
 }
 
+#[test]
+fn install_streams_packages_into_the_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("rustc")
+                .binary("bin/rustc", b"rustc binary")
+                .file("lib/librustc_driver.so", 0o644, &[42; 512 * 1024]),
+            TestPackage::new("cargo").binary("bin/cargo", b"cargo binary"),
+        ],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest =
+        test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc", "cargo"]);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+
+    let installation_id = criticalup_core::project_manifest::ProjectManifest::load(&manifest)
+        .unwrap()
+        .products()[0]
+        .installation_id();
+    let installation = construct_toolchains_product_path(&test_env, &installation_id.0);
+    assert_eq!(
+        b"rustc binary".as_slice(),
+        std::fs::read(installation.join("bin/rustc")).unwrap()
+    );
+    assert_eq!(
+        vec![42; 512 * 1024],
+        std::fs::read(installation.join("lib/librustc_driver.so")).unwrap()
+    );
+    assert!(installation
+        .join("share/criticaltrust/ferrocene/cargo.json")
+        .is_file());
+    // No archive is left behind in the installation, nor partially downloaded.
+    assert!(!installation.join("rustc.tar.xz").exists());
+    assert!(test_env.partial_downloads().is_empty());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path| {
+            std::fs::metadata(installation.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(0o755, mode("bin/cargo"));
+        assert_eq!(0o644, mode("lib/librustc_driver.so"));
+    }
+}
+
+#[test]
+fn install_fails_when_package_integrity_is_broken() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc")
+            .binary("bin/rustc", b"rustc binary")
+            .tamper("bin/rustc", b"malicious binary")],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+
+    // Neither the installation nor its staging directory are left behind.
+    assert_eq!(
+        0,
+        std::fs::read_dir(test_env.root().join("toolchains"))
+            .unwrap()
+            .count()
+    );
+}
+
+#[test]
+fn install_replaces_leftover_installation_dirs() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc").binary("bin/rustc", b"rustc binary")],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    // Simulate a directory left behind by an older release of criticalup, which didn't record the
+    // installation in the state.
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    let installation_id = criticalup_core::project_manifest::ProjectManifest::load(&manifest)
+        .unwrap()
+        .products()[0]
+        .installation_id();
+    let installation = construct_toolchains_product_path(&test_env, &installation_id.0);
+    std::fs::create_dir_all(installation.join("bin")).unwrap();
+    std::fs::write(installation.join("bin/leftover"), b"").unwrap();
+
+    let output = test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    assert!(installation.join("bin/rustc").is_file());
+    assert!(!installation.join("bin/leftover").exists());
+    // Only the installation itself is present, without staging directories.
+    assert_eq!(
+        1,
+        std::fs::read_dir(test_env.root().join("toolchains"))
+            .unwrap()
+            .count()
+    );
+}
+
+#[test]
+fn install_rejects_archives_not_matching_the_release_manifest() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc").binary("bin/rustc", b"rustc binary")],
+    );
+    // Corrupt the archive served by the download server, without changing its size.
+    test_env.edit_mock_download_server(|data| {
+        for archive in data.package_archives.values_mut() {
+            let last = archive.len() - 1;
+            archive[last] ^= 0xff;
+        }
+    });
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+
+    // Nothing was extracted from the rejected archive, and the archive itself was removed.
+    let installation_id = criticalup_core::project_manifest::ProjectManifest::load(&manifest)
+        .unwrap()
+        .products()[0]
+        .installation_id();
+    let installation = construct_toolchains_product_path(&test_env, &installation_id.0);
+    assert!(!installation.join("bin").exists());
+    assert!(test_env.partial_downloads().is_empty());
+}
+
+#[test]
+fn install_downloads_packages_concurrently() {
+    let test_env = TestEnvironment::prepare();
+    let packages = [
+        "rustc",
+        "cargo",
+        "rust-std-x86_64",
+        "rust-std-aarch64",
+        "rustfmt",
+    ];
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &packages
+            .iter()
+            .map(|name| TestPackage::new(name).file(&format!("share/{name}"), 0o644, b"data"))
+            .collect::<Vec<_>>(),
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &packages);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest)
+        .arg("--jobs")
+        .arg("3"));
+
+    let installation_id = criticalup_core::project_manifest::ProjectManifest::load(&manifest)
+        .unwrap()
+        .products()[0]
+        .installation_id();
+    let installation = construct_toolchains_product_path(&test_env, &installation_id.0);
+    for package in packages {
+        assert!(installation.join("share").join(package).is_file());
+    }
+}
+
+#[test]
+fn install_fails_with_invalid_jobs_env_var() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().env("CRITICALUP_JOBS", "0").arg("install"));
+}
+
+#[test]
+fn install_resumes_interrupted_downloads() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc").file("lib/librustc_driver.so", 0o644, &[42; 512 * 1024])],
+    );
+    test_env.edit_mock_download_server(|data| {
+        data.interrupt_package_downloads_after = Some(100);
+    });
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    let install = || {
+        test_env
+            .cmd()
+            // Retrying would resume the interrupted download right away.
+            .env("CRITICALUP_RETRIES", "0")
+            .arg("install")
+            .arg("--project")
+            .arg(&manifest)
+            .output()
+            .unwrap()
+    };
+
+    // The underlying error messages come from the HTTP client, and are not stable enough for a
+    // snapshot.
+    let output = install();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the download was interrupted"));
+
+    // The partial download is kept around, and the rest of it is fetched by the next attempt.
+    // Breaking the start of the archive on the server ensures the download is not restarted.
+    let partials = test_env.partial_downloads();
+    assert_eq!(1, partials.len());
+    assert_eq!(100, std::fs::metadata(&partials[0]).unwrap().len());
+    test_env.edit_mock_download_server(|data| {
+        data.interrupt_package_downloads_after = None;
+        for archive in data.package_archives.values_mut() {
+            archive[..100].fill(0);
+        }
+    });
+
+    assert!(install().status.success());
+    assert!(test_env.partial_downloads().is_empty());
+}
+
+#[test]
+fn install_prefers_tar_zst_archives() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("rustc")
+                .binary("bin/rustc", b"rustc binary")
+                .formats(&[ReleaseArtifactFormat::TarXz, ReleaseArtifactFormat::TarZst]),
+            TestPackage::new("cargo")
+                .binary("bin/cargo", b"cargo binary")
+                .formats(&[ReleaseArtifactFormat::TarZst]),
+        ],
+    );
+    // Remove the tar.xz archive from the server, to ensure the tar.zst one is the one downloaded.
+    test_env.edit_mock_download_server(|data| {
+        data.package_archives.retain(|key, _| key.3 == "tar.zst");
+    });
+    auth_set_with_valid_token(&test_env);
+
+    let manifest =
+        test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc", "cargo"]);
+    let output = test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+}
+
+#[test]
+fn install_fails_without_supported_archive_format() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc")
+            .binary("bin/rustc", b"rustc binary")
+            .formats(&[ReleaseArtifactFormat::Unknown])],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+}
+
+#[test]
+fn install_fails_with_package_missing_from_release() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc").binary("bin/rustc", b"rustc binary")],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["miri"]);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+}
+
+#[test]
+fn install_resolves_package_dependencies() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("cargo")
+                .binary("bin/cargo", b"cargo binary")
+                .dependency("rustc"),
+            TestPackage::new("rustc")
+                .binary("bin/rustc", b"rustc binary")
+                .dependency("rust-std"),
+            TestPackage::new("rust-std").file("lib/libstd.rlib", 0o644, b"std"),
+        ],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["cargo"]);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+
+    let installation_id = criticalup_core::project_manifest::ProjectManifest::load(&manifest)
+        .unwrap()
+        .products()[0]
+        .installation_id();
+    let installation = construct_toolchains_product_path(&test_env, &installation_id.0);
+    for file in ["bin/cargo", "bin/rustc", "lib/libstd.rlib"] {
+        assert!(installation.join(file).is_file(), "{file} is missing");
+    }
+
+    // The resolved packages are recorded in the state.
+    let state: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(test_env.root().join("state.json")).unwrap())
+            .unwrap();
+    let mut packages: Vec<_> = state
+        .pointer(&format!(
+            "/installations/{}/metadata/packages",
+            installation_id.0
+        ))
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|package| package["package"].as_str().unwrap())
+        .collect();
+    packages.sort();
+    assert_eq!(vec!["cargo", "rust-std", "rustc"], packages);
+}
+
+#[test]
+fn install_records_installation_metadata() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("rustc")
+                .binary("bin/rustc", b"rustc binary")
+                .dependency("rust-std"),
+            TestPackage::new("rust-std").file("lib/libstd.rlib", 0o644, b"std"),
+        ],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    let output = test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let installation_id = criticalup_core::project_manifest::ProjectManifest::load(&manifest)
+        .unwrap()
+        .products()[0]
+        .installation_id();
+    let state: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(test_env.root().join("state.json")).unwrap())
+            .unwrap();
+    assert_eq!(&json!(2), state.pointer("/version").unwrap());
+    let metadata = state
+        .pointer(&format!("/installations/{}/metadata", installation_id.0))
+        .unwrap();
+
+    assert_eq!(&json!("ferrocene"), &metadata["product"]);
+    assert_eq!(&json!("stable-1.0.0"), &metadata["release"]);
+    assert_eq!(64, metadata["commit"].as_str().unwrap().len());
+    assert_eq!(
+        &json!(env!("CARGO_PKG_VERSION")),
+        &metadata["criticalup_version"]
+    );
+    assert!(metadata["installed_at"].as_u64().unwrap() > 0);
+    // The package manifests are part of the installation too.
+    assert!(metadata["size"].as_u64().unwrap() > "rustc binarystd".len() as u64);
+
+    let packages = metadata["packages"].as_array().unwrap();
+    assert_eq!(
+        vec!["rust-std", "rustc"],
+        packages
+            .iter()
+            .map(|p| p["package"].as_str().unwrap())
+            .collect::<Vec<_>>()
+    );
+    for package in packages {
+        assert_eq!(&json!("tar.xz"), &package["format"]);
+        assert_eq!(64, package["sha256"].as_str().unwrap().len());
+    }
+}
+
+#[test]
+fn install_fails_with_dependency_missing_from_release() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("cargo")
+            .binary("bin/cargo", b"cargo binary")
+            .dependency("rustc")],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["cargo"]);
+    assert_output!(test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+}
+
+#[test]
+fn install_reuses_packages_of_existing_installations() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let first = test_env.install_project("first", &["rustc"]);
+
+    // Archives in the download cache would be reused anyway, clear it to only see new downloads.
+    let output = test_env.cmd().args(["cache", "clear"]).output().unwrap();
+    assert!(output.status.success());
+
+    let requests = test_env.requests_served_by_mock_download_server();
+    let second = test_env.project_manifest(
+        "second",
+        DEFAULT_PRODUCT,
+        DEFAULT_RELEASE,
+        &["rustc", "cargo"],
+    );
+    assert_output!(test_env.cmd().arg("install").arg("--project").arg(&second));
+    // Only the keys, the release manifest and the archive of cargo are downloaded.
+    assert_eq!(
+        requests + 3,
+        test_env.requests_served_by_mock_download_server()
+    );
+
+    let rustc = test_env.installation_dir(&second).join("bin/rustc");
+    assert_eq!(b"rustc binary".as_slice(), std::fs::read(&rustc).unwrap());
+    assert!(test_env
+        .installation_dir(&second)
+        .join("bin/cargo")
+        .is_file());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let original = test_env.installation_dir(&first).join("bin/rustc");
+        assert_eq!(
+            std::fs::metadata(original).unwrap().ino(),
+            std::fs::metadata(rustc).unwrap().ino()
+        );
+    }
+
+    // Removing a package from a project reuses all the remaining ones.
+    let requests = test_env.requests_served_by_mock_download_server();
+    let third = test_env.install_project("third", &["cargo"]);
+    assert_eq!(
+        requests + 2,
+        test_env.requests_served_by_mock_download_server()
+    );
+    assert!(test_env
+        .installation_dir(&third)
+        .join("bin/cargo")
+        .is_file());
+    assert!(!test_env.installation_dir(&third).join("bin/rustc").exists());
+}
+
+#[test]
+fn install_does_not_reuse_modified_packages() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let first = test_env.install_project("first", &["rustc"]);
+    let rustc = test_env.installation_dir(&first).join("bin/rustc");
+    std::fs::write(rustc, b"modified").unwrap();
+
+    // The modified package is downloaded again rather than failing the installation.
+    let second = test_env.project_manifest(
+        "second",
+        DEFAULT_PRODUCT,
+        DEFAULT_RELEASE,
+        &["rustc", "cargo"],
+    );
+    let output = test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&second)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("reusing component 'rustc'"));
+
+    assert_eq!(
+        b"rustc binary".as_slice(),
+        std::fs::read(test_env.installation_dir(&second).join("bin/rustc")).unwrap()
+    );
+}
+
+#[test]
+fn install_does_not_reuse_packages_of_other_releases() {
+    let test_env = TestEnvironment::prepare();
+    for release in ["stable-1.0.0", "stable-1.1.0"] {
+        test_env.publish_release(
+            "ferrocene",
+            release,
+            &[TestPackage::new("rustc").binary("bin/rustc", release.as_bytes())],
+        );
+    }
+    auth_set_with_valid_token(&test_env);
+
+    let old = test_env.project_manifest("old", "ferrocene", "stable-1.0.0", &["rustc"]);
+    let output = test_env
+        .cmd()
+        .arg("install")
+        .arg("--project")
+        .arg(&old)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let new = test_env.project_manifest("new", "ferrocene", "stable-1.1.0", &["rustc"]);
+    assert_output!(test_env.cmd().arg("install").arg("--project").arg(&new));
+
+    assert_eq!(
+        b"stable-1.1.0".as_slice(),
+        std::fs::read(test_env.installation_dir(&new).join("bin/rustc")).unwrap()
+    );
+}
+
+#[test]
+fn install_times_out_waiting_for_locked_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+
+    let manifest = test_env.project_manifest("proj", DEFAULT_PRODUCT, DEFAULT_RELEASE, &["rustc"]);
+    let id = criticalup_core::project_manifest::ProjectManifest::load(&manifest)
+        .unwrap()
+        .products()[0]
+        .installation_id();
+
+    // Simulate another process installing the same installation.
+    let lock_path = test_env
+        .root()
+        .join("locks")
+        .join(format!("installation-{}.lock", id.0));
+    std::fs::create_dir_all(lock_path.parent().unwrap()).unwrap();
+    let lock = std::fs::File::create(&lock_path).unwrap();
+    lock.lock().unwrap();
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(
+        &regex::escape(&test_env.root().display().to_string()),
+        "[root]",
+    );
+    settings.bind(|| {
+        assert_output!(test_env
+            .cmd()
+            .env("CRITICALUP_LOCK_TIMEOUT", "1")
+            .args(["install", "--project"])
+            .arg(&manifest));
+    });
+    assert!(!construct_toolchains_product_path(&test_env, &id.0).exists());
+
+    drop(lock);
+    test_env.install_project("proj", &["rustc"]);
+}
+
+#[test]
+fn install_force_replaces_the_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc"]);
+
+    let installation = test_env.installation_dir(&manifest);
+    std::fs::write(installation.join("leftover"), b"").unwrap();
+
+    assert_output!(test_env
+        .cmd()
+        .args(["install", "--force", "--project"])
+        .arg(&manifest));
+    assert_eq!(
+        b"rustc binary".as_slice(),
+        std::fs::read(installation.join("bin/rustc")).unwrap()
+    );
+    assert!(!installation.join("leftover").exists());
+    // Nothing is left behind next to the installation.
+    assert_eq!(
+        1,
+        std::fs::read_dir(installation.parent().unwrap())
+            .unwrap()
+            .count()
+    );
+}
+
 /// Sample test to run the command in test environment without any other computation
 #[test]
 #[ignore = "Testing `install` subcommand will be enabled at a later date"]
@@ -126,3 +771,126 @@ fn run_install_cmd(test_env: &TestEnvironment, manifest_path: &str) {
         String::from_utf8_lossy(&output.stderr)
     );
 }
+
+#[test]
+fn install_retries_transient_errors() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc").binary("bin/rustc", b"rustc")],
+    );
+    auth_set_with_valid_token(&test_env);
+    test_env.edit_mock_download_server(|data| {
+        data.transient_errors.extend([
+            TransientError {
+                status: 503,
+                retry_after: None,
+            },
+            TransientError {
+                status: 429,
+                retry_after: None,
+            },
+        ]);
+    });
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    assert_output!(test_env
+        .cmd()
+        .env("CRITICALUP_RETRIES", "2")
+        .arg("install")
+        .arg("--project")
+        .arg(&manifest));
+}
+
+#[test]
+fn install_offline_from_directory() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("rustc").binary("bin/rustc", b"rustc"),
+            TestPackage::new("cargo").binary("bin/cargo", b"cargo"),
+        ],
+    );
+    let offline_dir = test_env.root().join("offline");
+    test_env.export_offline_dir(&offline_dir);
+
+    // No authentication token is needed, as the download server is never contacted.
+    let manifest =
+        test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc", "cargo"]);
+    assert_output!(test_env
+        .cmd()
+        .args(["install", "--offline", "--from"])
+        .arg(&offline_dir)
+        .arg("--project")
+        .arg(&manifest));
+    assert_eq!(0, test_env.requests_served_by_mock_download_server());
+    assert!(std::fs::read_dir(test_env.root().join("toolchains"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path()
+        .join("bin")
+        .join("rustc")
+        .exists());
+}
+
+#[test]
+fn install_offline_rejects_tampered_archives() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[TestPackage::new("rustc").binary("bin/rustc", b"rustc")],
+    );
+    let offline_dir = test_env.root().join("offline");
+    test_env.export_offline_dir(&offline_dir);
+
+    let archive = offline_dir.join("v1/releases/ferrocene/stable-1.0.0/download/rustc/tar.xz");
+    let mut contents = std::fs::read(&archive).unwrap();
+    *contents.last_mut().unwrap() ^= 0xff;
+    std::fs::write(&archive, contents).unwrap();
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    assert_output!(test_env
+        .cmd()
+        .args(["install", "--offline", "--from", "offline"])
+        .arg("--project")
+        .arg(&manifest)
+        .current_dir(test_env.root()));
+    assert_eq!(0, test_env.requests_served_by_mock_download_server());
+}
+
+#[test]
+fn install_offline_requires_from() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["install", "--offline"]));
+}
+
+#[test]
+fn install_as_json() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    test_env.install_project("first", &["rustc"]);
+
+    let second =
+        test_env.project_manifest("second", "ferrocene", "stable-1.0.0", &["rustc", "cargo"]);
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(
+        &regex::escape(&test_env.root().display().to_string()),
+        "[root]",
+    );
+    settings.bind(|| {
+        assert_output!(test_env
+            .cmd()
+            .args(["install", "--format", "json", "--project"])
+            .arg(&second));
+        assert_output!(test_env
+            .cmd()
+            .args(["install", "--format", "json", "--project"])
+            .arg(&second));
+    });
+}
@@ -0,0 +1,58 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::assert_output;
+use crate::utils::{auth_set_with_valid_token, TestEnvironment, TestPackage};
+
+#[test]
+fn help_message() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["list", "--help"]));
+}
+
+#[test]
+fn list_without_installations() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().arg("list"));
+}
+
+#[test]
+fn list_installations() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("rustc").binary("bin/rustc", b"rustc binary"),
+            TestPackage::new("cargo").binary("bin/cargo", b"cargo binary"),
+        ],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let first = test_env.project_manifest("first", "ferrocene", "stable-1.0.0", &["rustc"]);
+    let second = test_env.project_manifest("second", "ferrocene", "stable-1.0.0", &["cargo"]);
+    let third = test_env.project_manifest("third", "ferrocene", "stable-1.0.0", &["cargo"]);
+    for manifest in [&first, &second, &third] {
+        let output = test_env
+            .cmd()
+            .args(["install", "--project"])
+            .arg(manifest)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+    }
+    std::fs::remove_file(&third).unwrap();
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(
+        &regex::escape(&test_env.root().display().to_string()),
+        "[root]",
+    );
+    // The size of installations depends on the size of the generated signatures.
+    settings.add_filter(r"\d+(\.\d)? (B|KiB)", "[size]");
+    settings.add_filter(r#""size": \d+"#, r#""size": [size]"#);
+    settings.bind(|| {
+        assert_output!(test_env.cmd().arg("list"));
+        assert_output!(test_env.cmd().args(["list", "--format", "json"]));
+    });
+}
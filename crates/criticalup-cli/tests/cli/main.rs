@@ -7,10 +7,17 @@ mod auth;
 mod auth_remove;
 mod auth_set;
 mod binary_proxies;
+mod cache;
 mod clean;
+mod config;
 mod install;
+mod list;
+mod mirror;
 mod remove;
+mod repair;
 mod root;
 mod run;
+mod show;
 mod utils;
+mod verify;
 mod which;
@@ -0,0 +1,160 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::assert_output;
+use crate::utils::{auth_set_with_valid_token, TestEnvironment, TestPackage};
+
+#[test]
+fn help_message() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["mirror", "--help"]));
+}
+
+#[test]
+fn mirror_and_install_offline() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+
+    assert_output!(test_env
+        .cmd()
+        .args([
+            "mirror",
+            "--product",
+            "ferrocene",
+            "--release",
+            "stable-1.0.0"
+        ])
+        .args(["--out", "mirror"])
+        .current_dir(test_env.root()));
+
+    let mirror = test_env.root().join("mirror");
+    let release = mirror.join("v1/releases/ferrocene/stable-1.0.0");
+    for archive in ["rustc/tar.xz", "rustc/tar.zst", "cargo/tar.xz"] {
+        assert!(release.join("download").join(archive).is_file());
+    }
+    assert!(mirror.join("v1/keys.json").is_file());
+    assert!(mirror
+        .join("v1/releases/ferrocene/stable-1.0.0.json")
+        .is_file());
+    // Archives are not added to the download cache, and no partial download is left behind.
+    assert!(!test_env.root().join("cache").exists());
+    assert!(!release.join("download/cargo/tar.xz.partial").exists());
+
+    let requests = test_env.requests_served_by_mock_download_server();
+    let manifest =
+        test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc", "cargo"]);
+    let output = test_env
+        .cmd()
+        .args(["install", "--offline", "--from"])
+        .arg(&mirror)
+        .arg("--project")
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(requests, test_env.requests_served_by_mock_download_server());
+}
+
+#[test]
+fn mirror_selected_packages() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+
+    assert_output!(test_env
+        .cmd()
+        .args([
+            "mirror",
+            "--product",
+            "ferrocene",
+            "--release",
+            "stable-1.0.0"
+        ])
+        .args(["--package", "cargo", "--out", "mirror"])
+        .current_dir(test_env.root()));
+
+    let downloads = test_env
+        .root()
+        .join("mirror/v1/releases/ferrocene/stable-1.0.0/download");
+    assert!(downloads.join("cargo/tar.xz").is_file());
+    assert!(!downloads.join("rustc").exists());
+}
+
+#[test]
+fn mirror_selected_packages_with_dependencies() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("rustc").binary("bin/rustc", b"rustc"),
+            TestPackage::new("cargo")
+                .binary("bin/cargo", b"cargo")
+                .dependency("rustc"),
+            TestPackage::new("clippy").binary("bin/clippy", b"clippy"),
+        ],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let output = test_env
+        .cmd()
+        .args(["mirror", "--product", "ferrocene", "--release"])
+        .args(["stable-1.0.0", "--package", "cargo", "--out", "mirror"])
+        .current_dir(test_env.root())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let downloads = test_env
+        .root()
+        .join("mirror/v1/releases/ferrocene/stable-1.0.0/download");
+    assert!(downloads.join("cargo/tar.xz").is_file());
+    assert!(downloads.join("rustc/tar.xz").is_file());
+    assert!(!downloads.join("clippy").exists());
+}
+
+#[test]
+fn mirror_fails_with_unknown_package() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+
+    assert_output!(test_env
+        .cmd()
+        .args([
+            "mirror",
+            "--product",
+            "ferrocene",
+            "--release",
+            "stable-1.0.0"
+        ])
+        .args(["--package", "clippy", "--out", "mirror"])
+        .current_dir(test_env.root()));
+    assert!(!test_env.root().join("mirror").exists());
+}
+
+#[test]
+fn mirror_rejects_tampered_archives() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    test_env.edit_mock_download_server(|data| {
+        for archive in data.package_archives.values_mut() {
+            *archive.last_mut().unwrap() ^= 0xff;
+        }
+    });
+
+    assert_output!(test_env
+        .cmd()
+        .args([
+            "mirror",
+            "--product",
+            "ferrocene",
+            "--release",
+            "stable-1.0.0"
+        ])
+        .args(["--package", "cargo", "--out", "mirror"])
+        .current_dir(test_env.root()));
+    // The release manifest is only written once all archives are verified.
+    assert!(!test_env
+        .root()
+        .join("mirror/v1/releases/ferrocene/stable-1.0.0.json")
+        .exists());
+}
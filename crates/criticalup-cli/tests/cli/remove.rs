@@ -71,3 +71,48 @@ fn remove_deletes_only_manifest_from_list_and_dir() {
     // Directory is gone.
     assert!(!product_toolchain_dir.exists());
 }
+
+#[test]
+fn remove_keeps_installations_used_by_other_projects() {
+    let test_env = TestEnvironment::prepare();
+    let manifest_path = std::env::current_dir()
+        .unwrap()
+        .join("tests/resources/criticalup.toml")
+        .canonicalize()
+        .unwrap();
+    let manifest =
+        criticalup_core::project_manifest::ProjectManifest::load(&manifest_path).unwrap();
+    let installation_id = manifest.products()[0].installation_id();
+
+    let other_manifest = test_env.root().join("other/criticalup.toml");
+    let content = json!({
+        "version": 1,
+        "installations": {
+            &installation_id.0: {
+                "binary_proxies": {},
+                "manifests": [manifest_path, other_manifest],
+            },
+        },
+    });
+    std::fs::write(test_env.root().join("state.json"), content.to_string()).unwrap();
+    let product_toolchain_dir =
+        construct_toolchains_product_path(&test_env, installation_id.0.as_str());
+    std::fs::create_dir_all(&product_toolchain_dir).unwrap();
+
+    assert_output!(test_env
+        .cmd()
+        .arg("remove")
+        .arg("--project")
+        .arg(&manifest_path));
+
+    let state: Value =
+        serde_json::from_slice(&std::fs::read(test_env.root().join("state.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        &json!([other_manifest]),
+        state
+            .pointer(&format!("/installations/{}/manifests", installation_id.0))
+            .unwrap()
+    );
+    assert!(product_toolchain_dir.exists());
+}
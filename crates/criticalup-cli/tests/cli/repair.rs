@@ -0,0 +1,76 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::assert_output;
+use crate::utils::TestEnvironment;
+use std::path::Path;
+
+#[test]
+fn help_message() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["repair", "--help"]));
+}
+
+fn repair(test_env: &TestEnvironment, manifest: &Path) -> std::process::Command {
+    let mut cmd = test_env.cmd();
+    cmd.arg("repair").arg("--project").arg(manifest);
+    cmd
+}
+
+#[test]
+fn repair_intact_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+
+    let requests = test_env.requests_served_by_mock_download_server();
+    assert_output!(repair(&test_env, &manifest));
+    // Only the keys and the release manifest are downloaded.
+    assert_eq!(
+        requests + 2,
+        test_env.requests_served_by_mock_download_server()
+    );
+}
+
+#[test]
+fn repair_downloads_damaged_packages_again() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+    let installation = test_env.installation_dir(&manifest);
+
+    // Archives in the download cache would be used otherwise.
+    let output = test_env.cmd().args(["cache", "clear"]).output().unwrap();
+    assert!(output.status.success());
+
+    let cargo = installation.join("bin/cargo");
+    std::fs::remove_file(&cargo).unwrap();
+    std::fs::write(&cargo, b"damaged").unwrap();
+
+    let requests = test_env.requests_served_by_mock_download_server();
+    assert_output!(repair(&test_env, &manifest));
+    // Only the keys, the release manifest and the archive of cargo are downloaded.
+    assert_eq!(
+        requests + 3,
+        test_env.requests_served_by_mock_download_server()
+    );
+    assert_eq!(b"cargo binary".as_slice(), std::fs::read(&cargo).unwrap());
+    assert_eq!(
+        b"rustc binary".as_slice(),
+        std::fs::read(installation.join("bin/rustc")).unwrap()
+    );
+}
+
+#[test]
+fn repair_missing_package_manifest() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+    let installation = test_env.installation_dir(&manifest);
+
+    let package_manifest = installation.join("share/criticaltrust/ferrocene/rustc.json");
+    std::fs::remove_file(&package_manifest).unwrap();
+
+    assert_output!(repair(&test_env, &manifest));
+    assert!(package_manifest.is_file());
+}
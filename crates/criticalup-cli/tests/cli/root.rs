@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::assert_output;
-use crate::utils::TestEnvironment;
+use crate::utils::{auth_set_with_valid_token, TestEnvironment, MOCK_AUTH_TOKENS};
 
 #[test]
 fn no_args() {
@@ -38,3 +38,99 @@ fn version_flags() {
 
     assert_output!(test_env.cmd().arg("--version"));
 }
+
+#[test]
+fn error_as_json() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args([
+        "--format",
+        "json",
+        "which",
+        "rustc",
+        "--project",
+        "missing.toml"
+    ]));
+}
+
+/// Logs include how long requests and commands took, which changes from one run to the next.
+fn log_settings(test_env: &TestEnvironment) -> insta::Settings {
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(
+        &regex::escape(&test_env.root().display().to_string()),
+        "[root]",
+    );
+    settings.add_filter(r"in \d+(\.\d+)?(ns|µs|ms|s)", "in [duration]");
+    settings
+}
+
+#[test]
+fn verbose_logs_redact_the_token() {
+    let test_env = TestEnvironment::prepare();
+    auth_set_with_valid_token(&test_env);
+
+    let output = test_env.cmd().args(["auth", "-vv"]).output().unwrap();
+    assert!(!String::from_utf8_lossy(&output.stderr).contains(MOCK_AUTH_TOKENS[0].0));
+
+    log_settings(&test_env).bind(|| assert_output!(test_env.cmd().args(["auth", "-vv"])));
+}
+
+#[test]
+fn log_level_from_env() {
+    let test_env = TestEnvironment::prepare();
+    auth_set_with_valid_token(&test_env);
+
+    let log = |env: &str, args: &[&str]| {
+        let output = test_env
+            .cmd()
+            .env("CRITICALUP_LOG", env)
+            .arg("auth")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert!(log("debug", &[]).contains("sending GET"));
+    assert!(!log("debug", &["-q"]).contains("sending GET"));
+    assert!(!log("info", &[]).contains("sending GET"));
+    assert!(log("info", &["-v"]).contains("sending GET"));
+
+    log_settings(&test_env).bind(|| {
+        assert_output!(test_env.cmd().env("CRITICALUP_LOG", "verbose").arg("auth"));
+    });
+}
+
+#[test]
+fn log_file_as_json() {
+    let test_env = TestEnvironment::prepare();
+    auth_set_with_valid_token(&test_env);
+
+    // The file has its own level, independent from the one of stderr.
+    let log_file = test_env.root().join("criticalup.log");
+    let output = test_env
+        .cmd()
+        .env("CRITICALUP_LOG_FILE", &log_file)
+        .env("CRITICALUP_LOG_FILE_LEVEL", "debug")
+        .args(["auth", "-q"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("sending GET"));
+
+    let records = std::fs::read_to_string(&log_file)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let request = records
+        .iter()
+        .find(|record| record["target"] == "criticalup_core::download_server_client")
+        .expect("requests were not logged");
+    assert_eq!(1, request["version"]);
+    assert_eq!("debug", request["level"]);
+    assert!(request["timestamp"].as_f64().unwrap() > 0.0);
+    assert!(request["message"]
+        .as_str()
+        .unwrap()
+        .contains("(authorization: [redacted])"));
+}
@@ -0,0 +1,69 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::assert_output;
+use crate::utils::{auth_set_with_valid_token, TestEnvironment, TestPackage};
+
+#[test]
+fn help_message() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["show", "--help"]));
+}
+
+#[test]
+fn show_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_release(
+        "ferrocene",
+        "stable-1.0.0",
+        &[
+            TestPackage::new("rustc")
+                .binary("bin/rustc", b"rustc binary")
+                .binary("bin/rustdoc", b"rustdoc binary")
+                .dependency("rust-std"),
+            TestPackage::new("rust-std").file("lib/libstd.rlib", 0o644, b"std"),
+        ],
+    );
+    auth_set_with_valid_token(&test_env);
+
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+    let output = test_env
+        .cmd()
+        .args(["install", "--project"])
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(
+        &regex::escape(&test_env.root().display().to_string()),
+        "[root]",
+    );
+    settings.bind(|| {
+        assert_output!(test_env.cmd().args(["show", "--project"]).arg(&manifest));
+        assert_output!(test_env
+            .cmd()
+            .args(["show", "--format", "json", "--project"])
+            .arg(&manifest));
+    });
+}
+
+#[test]
+fn show_not_installed() {
+    let test_env = TestEnvironment::prepare();
+    let manifest = test_env.project_manifest("proj", "ferrocene", "stable-1.0.0", &["rustc"]);
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(
+        &regex::escape(&test_env.root().display().to_string()),
+        "[root]",
+    );
+    settings.bind(|| {
+        assert_output!(test_env.cmd().args(["show", "--project"]).arg(&manifest));
+        assert_output!(test_env
+            .cmd()
+            .args(["show", "--format", "json", "--project"])
+            .arg(&manifest));
+    });
+}
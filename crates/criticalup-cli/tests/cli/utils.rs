@@ -2,15 +2,25 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use criticaltrust::keys::{EphemeralKeyPair, KeyAlgorithm, KeyPair, KeyRole, PublicKey};
-use criticaltrust::manifests::{Release, ReleaseManifest};
+use criticaltrust::manifests::{
+    KeysManifest, ManifestVersion, Package, PackageFile, PackageManifest, Release, ReleaseArtifact,
+    ReleaseArtifactFormat, ReleaseManifest, ReleasePackage,
+};
 use criticaltrust::signatures::SignedPayload;
-use mock_download_server::{AuthenticationToken, MockServer};
+use criticalup_core::config::SETTINGS;
+use mock_download_server::{AuthenticationToken, Data, MockServer};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
 use std::io::{Seek, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output, Stdio};
 use tempfile::TempDir;
 
+/// Product published by [`TestEnvironment::publish_default_release`].
+pub(crate) const DEFAULT_PRODUCT: &str = "ferrocene";
+/// Release published by [`TestEnvironment::publish_default_release`].
+pub(crate) const DEFAULT_RELEASE: &str = "stable-1.0.0";
+
 pub(crate) const MOCK_AUTH_TOKENS: &[(&str, AuthenticationToken)] = &[
     (
         "criticalup_token_000000000",
@@ -59,25 +69,30 @@ pub(crate) fn mock_release_manifests() -> Vec<(&'static str, &'static str, Relea
 pub(crate) struct TestEnvironment {
     root: TempDir,
     trust_root: PublicKey,
+    releases_key: EphemeralKeyPair,
+    packages_key: EphemeralKeyPair,
     server: MockServer,
     customer_portal_url: String,
 }
 
 impl TestEnvironment {
     pub(crate) fn prepare() -> Self {
-        let keypair = EphemeralKeyPair::generate(
-            KeyAlgorithm::EcdsaP256Sha256Asn1SpkiDer,
-            KeyRole::Root,
-            None,
-        )
-        .unwrap();
+        let generate = |role| {
+            EphemeralKeyPair::generate(KeyAlgorithm::EcdsaP256Sha256Asn1SpkiDer, role, None)
+                .unwrap()
+        };
+        let keypair = generate(KeyRole::Root);
+        let releases_key = generate(KeyRole::Releases);
+        let packages_key = generate(KeyRole::Packages);
 
         let root = TempDir::new_in(std::env::current_dir().unwrap()).unwrap();
 
         TestEnvironment {
             root,
             trust_root: keypair.public().clone(),
-            server: setup_mock_server(&keypair),
+            server: setup_mock_server(&keypair, &[&releases_key, &packages_key]),
+            releases_key,
+            packages_key,
             customer_portal_url: "https://customers-test.ferrocene.dev".into(),
         }
     }
@@ -88,6 +103,14 @@ impl TestEnvironment {
 
     pub(crate) fn cmd(&self) -> Command {
         let mut command = Command::new(env!("CARGO_BIN_EXE_criticalup-test"));
+        // Settings and logging configured in the environment running the tests would otherwise
+        // override the ones of the test.
+        for setting in SETTINGS {
+            command.env_remove(setting.env_var);
+        }
+        command.env_remove("CRITICALUP_LOG");
+        command.env_remove("CRITICALUP_LOG_FILE");
+        command.env_remove("CRITICALUP_LOG_FILE_LEVEL");
         command.env("CRITICALUP_ROOT", self.root.path());
         command.env("CRITICALUP_TEST_DOWNLOAD_SERVER_URL", self.server.url());
         command.env(
@@ -112,11 +135,311 @@ impl TestEnvironment {
         self.server.served_requests_count()
     }
 
+    pub(crate) fn edit_mock_download_server(&self, f: impl FnOnce(&mut Data)) {
+        self.server.edit_data(f);
+    }
+
     pub(crate) fn revoke_token(&self, token: &str) {
         self.server.edit_data(|data| {
             data.tokens.remove(token);
         });
     }
+
+    /// Publish a signed release on the mock download server, with archives for each of the
+    /// provided packages.
+    pub(crate) fn publish_release(&self, product: &str, release: &str, packages: &[TestPackage]) {
+        // Packages are built from the same commit as the release they're part of, and each
+        // release is built from a different commit.
+        let commit: String = Sha256::digest(format!("{product}/{release}"))
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        let mut release_packages = Vec::new();
+        let mut archives = Vec::new();
+        for package in packages {
+            let mut artifacts = Vec::new();
+            for format in &package.formats {
+                let archive = package.build_archive(product, &commit, format, &self.packages_key);
+                artifacts.push(ReleaseArtifact {
+                    format: format.clone(),
+                    size: archive.len(),
+                    sha256: Sha256::digest(&archive).to_vec(),
+                });
+                archives.push((package.name.clone(), format.to_string(), archive));
+            }
+            release_packages.push(ReleasePackage {
+                package: package.name.clone(),
+                artifacts,
+                dependencies: package.dependencies.clone(),
+            });
+        }
+
+        let mut signed = SignedPayload::new(&Release {
+            product: product.into(),
+            release: release.into(),
+            commit,
+            packages: release_packages,
+        })
+        .unwrap();
+        signed.add_signature(&self.releases_key).unwrap();
+
+        self.server.edit_data(|data| {
+            data.release_manifests.insert(
+                (product.into(), release.into()),
+                ReleaseManifest {
+                    version: ManifestVersion,
+                    signed,
+                },
+            );
+            for (package, format, archive) in archives {
+                data.package_archives
+                    .insert((product.into(), release.into(), package, format), archive);
+            }
+        });
+    }
+
+    /// Paths of the partial downloads in the download cache.
+    pub(crate) fn partial_downloads(&self) -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(self.root().join("cache")) else {
+            return Vec::new();
+        };
+        entries
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "partial"))
+            .collect()
+    }
+
+    /// Copy everything published on the mock download server to `dir`, laid out like the
+    /// directories used by `criticalup install --offline`.
+    pub(crate) fn export_offline_dir(&self, dir: &Path) {
+        let write = |path: PathBuf, contents: &[u8]| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        let releases = dir.join("v1").join("releases");
+
+        self.server.edit_data(|data| {
+            let keys = KeysManifest {
+                version: ManifestVersion,
+                keys: data.keys.clone(),
+            };
+            write(
+                dir.join("v1").join("keys.json"),
+                &serde_json::to_vec(&keys).unwrap(),
+            );
+            for ((product, release), manifest) in &data.release_manifests {
+                write(
+                    releases.join(product).join(format!("{release}.json")),
+                    &serde_json::to_vec(manifest).unwrap(),
+                );
+            }
+            for ((product, release, package, format), archive) in &data.package_archives {
+                let path = releases.join(product).join(release).join("download");
+                write(path.join(package).join(format), archive);
+            }
+        });
+    }
+
+    /// Write a `criticalup.toml` requesting the provided packages in a new project directory,
+    /// returning the path of the manifest.
+    pub(crate) fn project_manifest(
+        &self,
+        name: &str,
+        product: &str,
+        release: &str,
+        packages: &[&str],
+    ) -> PathBuf {
+        let dir = self.root().join("projects").join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("criticalup.toml");
+        std::fs::write(
+            &path,
+            format!(
+                "manifest-version = 1\n\n[products.{product}]\nrelease = \"{release}\"\npackages = {packages:?}\n"
+            ),
+        )
+        .unwrap();
+        path
+    }
+
+    /// Publish the `stable-1.0.0` release of `ferrocene`, with a `rustc` package available in
+    /// every archive format and a `cargo` one, and authenticate with a valid token.
+    pub(crate) fn publish_default_release(&self) {
+        self.publish_release(
+            DEFAULT_PRODUCT,
+            DEFAULT_RELEASE,
+            &[
+                TestPackage::new("rustc")
+                    .binary("bin/rustc", b"rustc binary")
+                    .file("lib/librustc_driver.so", 0o644, b"driver")
+                    .formats(&[ReleaseArtifactFormat::TarXz, ReleaseArtifactFormat::TarZst]),
+                TestPackage::new("cargo").binary("bin/cargo", b"cargo binary"),
+            ],
+        );
+        auth_set_with_valid_token(self);
+    }
+
+    /// Install a project named `name` requesting `packages` of the release published by
+    /// [`TestEnvironment::publish_default_release`], returning the path of its manifest.
+    pub(crate) fn install_project(&self, name: &str, packages: &[&str]) -> PathBuf {
+        let manifest = self.project_manifest(name, DEFAULT_PRODUCT, DEFAULT_RELEASE, packages);
+        let output = self
+            .cmd()
+            .arg("install")
+            .arg("--project")
+            .arg(&manifest)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "failed to install {name}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        manifest
+    }
+
+    /// Directory of the installation of the project whose manifest is at `manifest`.
+    pub(crate) fn installation_dir(&self, manifest: &Path) -> PathBuf {
+        let id = criticalup_core::project_manifest::ProjectManifest::load(manifest)
+            .unwrap()
+            .products()[0]
+            .installation_id();
+        construct_toolchains_product_path(self, &id.0)
+    }
+}
+
+/// Package published on the mock download server by [`TestEnvironment::publish_release`].
+pub(crate) struct TestPackage {
+    name: String,
+    files: Vec<TestPackageFile>,
+    formats: Vec<ReleaseArtifactFormat>,
+    dependencies: Vec<String>,
+}
+
+struct TestPackageFile {
+    path: String,
+    mode: u32,
+    contents: Vec<u8>,
+    needs_proxy: bool,
+    /// Contents stored in the archive, if they differ from the ones in the package manifest.
+    tampered: Option<Vec<u8>>,
+}
+
+impl TestPackage {
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            files: Vec::new(),
+            formats: vec![ReleaseArtifactFormat::TarXz],
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Declare a dependency on another package in the release manifest.
+    pub(crate) fn dependency(mut self, package: &str) -> Self {
+        self.dependencies.push(package.into());
+        self
+    }
+
+    /// Archive formats the package is published in, `tar.xz` only by default.
+    pub(crate) fn formats(mut self, formats: &[ReleaseArtifactFormat]) -> Self {
+        self.formats = formats.to_vec();
+        self
+    }
+
+    pub(crate) fn file(mut self, path: &str, mode: u32, contents: &[u8]) -> Self {
+        self.files.push(TestPackageFile {
+            path: path.into(),
+            mode,
+            contents: contents.into(),
+            needs_proxy: false,
+            tampered: None,
+        });
+        self
+    }
+
+    /// Add an executable that criticalup should create a binary proxy for.
+    pub(crate) fn binary(mut self, path: &str, contents: &[u8]) -> Self {
+        self = self.file(path, 0o755, contents);
+        self.files.last_mut().unwrap().needs_proxy = true;
+        self
+    }
+
+    /// Store different contents in the archive than the ones recorded in the package manifest.
+    pub(crate) fn tamper(mut self, path: &str, contents: &[u8]) -> Self {
+        let file = self.files.iter_mut().find(|f| f.path == path).unwrap();
+        file.tampered = Some(contents.into());
+        self
+    }
+
+    fn build_archive(
+        &self,
+        product: &str,
+        commit: &str,
+        format: &ReleaseArtifactFormat,
+        key: &EphemeralKeyPair,
+    ) -> Vec<u8> {
+        let mut signed = SignedPayload::new(&Package {
+            product: product.into(),
+            package: self.name.clone(),
+            commit: commit.into(),
+            files: self
+                .files
+                .iter()
+                .map(|file| PackageFile {
+                    path: file.path.clone(),
+                    posix_mode: file.mode,
+                    sha256: Sha256::digest(&file.contents).to_vec(),
+                    needs_proxy: file.needs_proxy,
+                })
+                .collect(),
+            managed_prefixes: Vec::new(),
+        })
+        .unwrap();
+        signed.add_signature(key).unwrap();
+        let manifest = serde_json::to_vec(&PackageManifest {
+            version: ManifestVersion,
+            signed,
+        })
+        .unwrap();
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let manifest_path = format!("share/criticaltrust/{product}/{}.json", self.name);
+        let files = self
+            .files
+            .iter()
+            .map(|f| {
+                (
+                    f.path.as_str(),
+                    f.mode,
+                    f.tampered.as_ref().unwrap_or(&f.contents),
+                )
+            })
+            .chain(std::iter::once((manifest_path.as_str(), 0o644, &manifest)));
+        for (path, mode, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(mode);
+            header.set_mtime(1_700_000_000);
+            builder
+                .append_data(&mut header, path, contents.as_slice())
+                .unwrap();
+        }
+        let tarball = builder.into_inner().unwrap();
+
+        match format {
+            ReleaseArtifactFormat::TarXz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(&tarball).unwrap();
+                encoder.finish().unwrap()
+            }
+            ReleaseArtifactFormat::TarZst => zstd::encode_all(tarball.as_slice(), 0).unwrap(),
+            // Servers could offer formats unknown to this release of criticalup.
+            _ => b"unknown archive format".to_vec(),
+        }
+    }
 }
 
 pub(crate) fn stdin(content: &str) -> Stdio {
@@ -127,11 +450,16 @@ pub(crate) fn stdin(content: &str) -> Stdio {
     file.into()
 }
 
-fn setup_mock_server(keypair: &dyn KeyPair) -> MockServer {
+fn setup_mock_server(keypair: &dyn KeyPair, keys: &[&dyn KeyPair]) -> MockServer {
     let mut server = mock_download_server::new();
     for (token, data) in MOCK_AUTH_TOKENS {
         server = server.add_token(token, data.clone());
     }
+    for key in keys {
+        let mut payload = SignedPayload::new(key.public()).unwrap();
+        payload.add_signature(keypair).unwrap();
+        server = server.add_key(payload);
+    }
     for (product, release, mut manifest) in mock_release_manifests() {
         manifest.signed.add_signature(keypair).unwrap();
         server =
@@ -195,6 +523,9 @@ macro_rules! assert_output {
             "caused by: No such file or directory (os error 2)",
         );
 
+        // The mock download server listens on a random port.
+        settings.add_filter(r"http://127\.0\.0\.1:\d+/", "http://127.0.0.1:[port]/");
+
         #[cfg(windows)]
         settings.add_filter("exit code: ", "exit status: ");
         #[cfg(windows)]
@@ -0,0 +1,112 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::assert_output;
+use crate::utils::{TestEnvironment, DEFAULT_PRODUCT, DEFAULT_RELEASE};
+use std::path::Path;
+
+#[test]
+fn help_message() {
+    let test_env = TestEnvironment::prepare();
+    assert_output!(test_env.cmd().args(["verify", "--help"]));
+}
+
+fn verify(test_env: &TestEnvironment, manifest: &Path) -> std::process::Command {
+    let mut cmd = test_env.cmd();
+    cmd.arg("verify").arg("--project").arg(manifest);
+    cmd
+}
+
+#[test]
+fn verify_intact_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+
+    assert_output!(verify(&test_env, &manifest));
+}
+
+#[test]
+fn verify_modified_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+    let installation = test_env.installation_dir(&manifest);
+
+    std::fs::write(installation.join("bin/cargo"), b"modified").unwrap();
+    std::fs::remove_file(installation.join("lib/librustc_driver.so")).unwrap();
+    std::fs::write(installation.join("bin/extra"), b"").unwrap();
+
+    assert_output!(verify(&test_env, &manifest));
+}
+
+#[test]
+fn verify_installation_with_missing_package() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+    let installation = test_env.installation_dir(&manifest);
+
+    // Without its package manifest, the files of the package are not checked anymore.
+    std::fs::remove_file(installation.join("bin/cargo")).unwrap();
+    std::fs::remove_file(installation.join("share/criticaltrust/ferrocene/cargo.json")).unwrap();
+
+    assert_output!(verify(&test_env, &manifest));
+}
+
+#[test]
+fn verify_offline_with_keys_from_directory() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+    let offline_dir = test_env.root().join("offline");
+    test_env.export_offline_dir(&offline_dir);
+
+    let requests = test_env.requests_served_by_mock_download_server();
+    assert_output!(verify(&test_env, &manifest)
+        .args(["--offline", "--from"])
+        .arg(&offline_dir));
+    assert_eq!(requests, test_env.requests_served_by_mock_download_server());
+}
+
+#[test]
+#[cfg(unix)]
+fn verify_installation_with_modified_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+    let installation = test_env.installation_dir(&manifest);
+
+    let rustc = installation.join("bin/rustc");
+    std::fs::set_permissions(&rustc, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+    assert_output!(verify(&test_env, &manifest));
+}
+
+#[test]
+fn verify_missing_installation() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.project_manifest(
+        "proj",
+        DEFAULT_PRODUCT,
+        DEFAULT_RELEASE,
+        &["rustc", "cargo"],
+    );
+
+    assert_output!(verify(&test_env, &manifest));
+}
+
+#[test]
+fn verify_modified_installation_as_json() {
+    let test_env = TestEnvironment::prepare();
+    test_env.publish_default_release();
+    let manifest = test_env.install_project("proj", &["rustc", "cargo"]);
+    let installation = test_env.installation_dir(&manifest);
+
+    std::fs::write(installation.join("bin/cargo"), b"modified").unwrap();
+
+    assert_output!(verify(&test_env, &manifest).args(["--format", "json"]));
+}
@@ -65,3 +65,26 @@ fn which_run_binary_does_not_exists() {
         .cmd()
         .args(["which", "rustc", "--project", manifest_path]));
 }
+
+#[test]
+fn which_as_json() {
+    let test_env = TestEnvironment::prepare();
+
+    let mut current_dir =
+        std::env::current_dir().expect("could not read current directory in the test.");
+    current_dir.push("tests/resources/criticalup-which.toml");
+
+    let p = ProjectManifest::load(current_dir.as_path()).expect("could not load project manifest");
+    let id_hash = p.products()[0].installation_id().0;
+
+    let product_toolchain_dir = construct_toolchains_product_path(&test_env, id_hash.as_str());
+    let product_toolchain_bin_dir = product_toolchain_dir.join("bin");
+    std::fs::create_dir_all(&product_toolchain_bin_dir)
+        .expect("could not create product directory");
+    let _ = File::create(product_toolchain_bin_dir.join("rustc")).unwrap();
+
+    assert_output!(test_env
+        .cmd()
+        .args(["which", "rustc", "--format", "json", "--project"])
+        .arg(&current_dir));
+}
@@ -263,6 +263,7 @@ mod tests {
                 product: String::new(),
                 package: String::new(),
                 proxies_paths,
+                proxies_sha256: BTreeMap::new(),
             }]
         }
 
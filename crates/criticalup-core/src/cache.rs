@@ -0,0 +1,322 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Cache of downloaded package archives, shared by all installations.
+//!
+//! Archives are stored by the SHA256 checksum signed in the release manifest, so the same archive
+//! is only ever downloaded once, regardless of which project or release requested it. The cache
+//! is kept below its maximum size by removing the least recently used archives first.
+
+use crate::config::Config;
+use crate::errors::Error;
+use crate::utils::hex_encode;
+use criticaltrust::manifests::ReleaseArtifact;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    dir: PathBuf,
+}
+
+/// Archive stored in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    /// Hex-encoded SHA256 checksum of the archive.
+    pub sha256: String,
+    /// Format of the archive, for example `tar.xz`.
+    pub format: String,
+    pub size: ByteSize,
+    pub last_used: SystemTime,
+}
+
+impl DownloadCache {
+    pub fn new(config: &Config) -> Self {
+        DownloadCache {
+            dir: config.paths.cache_dir.clone(),
+        }
+    }
+
+    /// Path the archive of `artifact` is stored at, whether it's cached or not.
+    pub fn path(&self, artifact: &ReleaseArtifact) -> PathBuf {
+        self.dir.join(format!(
+            "{}.{}",
+            hex_encode(&artifact.sha256),
+            artifact.format
+        ))
+    }
+
+    /// Path the archive of `artifact` is downloaded to before being added to the cache. Partial
+    /// downloads are kept next to the cached archives, so that they can be moved into the cache
+    /// without copying them, and they're ignored by everything else in the cache.
+    pub fn partial_path(&self, artifact: &ReleaseArtifact) -> PathBuf {
+        let mut path = self.path(artifact).into_os_string();
+        path.push(".partial");
+        path.into()
+    }
+
+    /// Return the path of the cached archive of `artifact`, if it's in the cache.
+    ///
+    /// Cached archives are verified against the checksum in the release manifest before being
+    /// returned, and removed from the cache if they don't match (for example because they were
+    /// corrupted on disk).
+    pub fn get(&self, artifact: &ReleaseArtifact) -> Result<Option<PathBuf>, Error> {
+        let path = self.path(artifact);
+        let err = |e| Error::CacheAccessFailed(path.clone(), e);
+
+        let mut file = match File::options().append(true).read(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(err(e)),
+        };
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut file, &mut hasher).map_err(err)?;
+        if size != artifact.size as u64 || hasher.finalize().as_slice() != artifact.sha256 {
+            log::warn!(
+                "removing corrupt archive {} from the download cache",
+                path.display()
+            );
+            drop(file);
+            std::fs::remove_file(&path).map_err(err)?;
+            return Ok(None);
+        }
+
+        // The modification time tracks when archives were last used, to prune the least recently
+        // used archives first.
+        file.set_modified(SystemTime::now()).map_err(err)?;
+        Ok(Some(path))
+    }
+
+    /// List all the archives in the cache, starting from the most recently used.
+    pub fn list(&self) -> Result<Vec<CacheEntry>, Error> {
+        let err = |e| Error::CacheAccessFailed(self.dir.clone(), e);
+        let dir = match std::fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(err(e)),
+        };
+
+        let mut entries = Vec::new();
+        for dir_entry in dir {
+            let dir_entry = dir_entry.map_err(err)?;
+            let path = dir_entry.path();
+            // Ignore anything not created by the cache.
+            let Some((sha256, format)) = parse_file_name(&path) else {
+                continue;
+            };
+            let metadata = dir_entry
+                .metadata()
+                .map_err(|e| Error::CacheAccessFailed(path.clone(), e))?;
+            if !metadata.is_file() {
+                continue;
+            }
+            entries.push(CacheEntry {
+                sha256: sha256.into(),
+                format: format.into(),
+                size: ByteSize(metadata.len()),
+                last_used: metadata
+                    .modified()
+                    .map_err(|e| Error::CacheAccessFailed(path.clone(), e))?,
+                path,
+            });
+        }
+
+        entries.sort_by(|a, b| b.last_used.cmp(&a.last_used).then(a.path.cmp(&b.path)));
+        Ok(entries)
+    }
+
+    /// Remove the least recently used archives until the cache is no larger than `max_size`,
+    /// returning the removed archives.
+    pub fn prune(&self, max_size: ByteSize) -> Result<Vec<CacheEntry>, Error> {
+        let mut entries = self.list()?;
+        let mut size: u64 = entries.iter().map(|entry| entry.size.0).sum();
+
+        let mut removed = Vec::new();
+        while size > max_size.0 {
+            let Some(entry) = entries.pop() else { break };
+            std::fs::remove_file(&entry.path)
+                .map_err(|e| Error::CacheAccessFailed(entry.path.clone(), e))?;
+            size -= entry.size.0;
+            removed.push(entry);
+        }
+        Ok(removed)
+    }
+
+    /// Remove all the archives in the cache, returning them.
+    pub fn clear(&self) -> Result<Vec<CacheEntry>, Error> {
+        self.prune(ByteSize(0))
+    }
+}
+
+/// Split the name of a cached archive into its checksum and format.
+fn parse_file_name(path: &Path) -> Option<(&str, &str)> {
+    let (sha256, format) = path.file_name()?.to_str()?.split_once('.')?;
+    let is_sha256 = sha256.len() == 64 && sha256.bytes().all(|b| b.is_ascii_hexdigit());
+    let is_partial = path.extension().is_some_and(|ext| ext == "partial");
+    (is_sha256 && !is_partial).then_some((sha256, format))
+}
+
+/// Size in bytes, parsed from and displayed with binary unit suffixes (like `10G` or `1.5 MiB`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+const UNITS: &[(&str, &str)] = &[("K", "KiB"), ("M", "MiB"), ("G", "GiB"), ("T", "TiB")];
+
+impl FromStr for ByteSize {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number.parse().map_err(|_| ())?;
+
+        let unit = unit.trim().trim_end_matches(['B', 'b']);
+        let multiplier = if unit.is_empty() {
+            1
+        } else {
+            let unit = unit.trim_end_matches('i').to_ascii_uppercase();
+            let exponent = UNITS
+                .iter()
+                .position(|(short, _)| *short == unit)
+                .ok_or(())?;
+            1024u64.pow(exponent as u32 + 1)
+        };
+        number.checked_mul(multiplier).map(ByteSize).ok_or(())
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut size = self.0 as f64;
+        let mut unit = "B";
+        for (_, long) in UNITS {
+            if size < 1024.0 {
+                break;
+            }
+            size /= 1024.0;
+            unit = long;
+        }
+        if unit == "B" {
+            write!(f, "{} B", self.0)
+        } else {
+            write!(f, "{size:.1} {unit}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestEnvironment;
+    use criticaltrust::manifests::ReleaseArtifactFormat;
+    use std::time::Duration;
+
+    fn artifact(contents: &[u8]) -> ReleaseArtifact {
+        ReleaseArtifact {
+            format: ReleaseArtifactFormat::TarXz,
+            size: contents.len(),
+            sha256: Sha256::digest(contents).to_vec(),
+        }
+    }
+
+    fn insert(cache: &DownloadCache, contents: &[u8], last_used_secs_ago: u64) -> PathBuf {
+        let path = cache.path(&artifact(contents));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        File::options()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(last_used_secs_ago))
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_get() {
+        let test_env = TestEnvironment::prepare();
+        let cache = DownloadCache::new(test_env.config());
+
+        assert_eq!(None, cache.get(&artifact(b"hello")).unwrap());
+
+        let path = insert(&cache, b"hello", 3600);
+        let before = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(Some(path.clone()), cache.get(&artifact(b"hello")).unwrap());
+        assert!(std::fs::metadata(&path).unwrap().modified().unwrap() > before);
+
+        // Corrupt archives are removed from the cache.
+        std::fs::write(&path, b"world").unwrap();
+        assert_eq!(None, cache.get(&artifact(b"hello")).unwrap());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_list_and_prune() {
+        let test_env = TestEnvironment::prepare();
+        let cache = DownloadCache::new(test_env.config());
+        assert!(cache.list().unwrap().is_empty());
+
+        let oldest = insert(&cache, &[1; 100], 300);
+        let newest = insert(&cache, &[2; 200], 100);
+        let middle = insert(&cache, &[3; 300], 200);
+        // Files not created by the cache are ignored.
+        std::fs::write(cache.dir.join("unrelated"), b"").unwrap();
+        std::fs::write(cache.dir.join(format!("{}.partial", "a".repeat(64))), b"").unwrap();
+
+        let paths = |entries: Vec<CacheEntry>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect::<Vec<_>>()
+        };
+        let entries = cache.list().unwrap();
+        assert_eq!(ByteSize(200), entries[0].size);
+        assert_eq!("tar.xz", entries[0].format);
+        assert_eq!(hex_encode(&Sha256::digest([2; 200])), entries[0].sha256);
+        assert_eq!(
+            vec![newest.clone(), middle.clone(), oldest.clone()],
+            paths(entries)
+        );
+
+        assert!(cache.prune(ByteSize(600)).unwrap().is_empty());
+        assert_eq!(vec![oldest], paths(cache.prune(ByteSize(550)).unwrap()));
+        assert_eq!(vec![middle], paths(cache.prune(ByteSize(299)).unwrap()));
+        assert_eq!(vec![newest], paths(cache.clear().unwrap()));
+        assert!(cache.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_byte_size_parse() {
+        let parse = |s: &str| s.parse::<ByteSize>().map(|size| size.0);
+
+        assert_eq!(Ok(0), parse("0"));
+        assert_eq!(Ok(1234), parse("1234"));
+        assert_eq!(Ok(1234), parse("1234B"));
+        assert_eq!(Ok(2048), parse("2K"));
+        assert_eq!(Ok(2048), parse("2KiB"));
+        assert_eq!(Ok(3 * 1024 * 1024), parse("3 MiB"));
+        assert_eq!(Ok(10 * 1024 * 1024 * 1024), parse("10G"));
+        assert_eq!(Ok(10 * 1024 * 1024 * 1024), parse("10gb"));
+        assert_eq!(Ok(1024u64.pow(4)), parse("1T"));
+
+        for invalid in ["", "G", "-1", "1.5G", "10X", "99999999999T"] {
+            assert_eq!(Err(()), parse(invalid), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_byte_size_display() {
+        assert_eq!("0 B", ByteSize(0).to_string());
+        assert_eq!("1023 B", ByteSize(1023).to_string());
+        assert_eq!("1.0 KiB", ByteSize(1024).to_string());
+        assert_eq!("1.5 MiB", ByteSize(1024 * 1024 * 3 / 2).to_string());
+        assert_eq!("10.0 GiB", ByteSize(10 * 1024 * 1024 * 1024).to_string());
+        assert_eq!("2048.0 TiB", ByteSize(2 * 1024u64.pow(5)).to_string());
+    }
+}
@@ -0,0 +1,325 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! User configuration file, stored as `config.toml` in the criticalup root. Every setting in it
+//! can also be configured through an environment variable, which takes precedence over the file.
+//!
+//! The file is edited with `toml_edit`, so comments and formatting added by hand are preserved
+//! when `criticalup config set` changes it.
+
+use crate::cache::ByteSize;
+use crate::errors::Error;
+use crate::utils::write_file_atomically;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use toml_edit::{value, Document, Item};
+
+/// Setting that can be stored in the configuration file.
+#[derive(Debug)]
+pub struct Setting {
+    /// Name of the setting in the configuration file.
+    pub key: &'static str,
+    /// Environment variable overriding the value in the configuration file.
+    pub env_var: &'static str,
+    kind: Kind,
+    /// Check whether a value is valid for the setting.
+    validate: fn(&str) -> bool,
+}
+
+/// Type of TOML value the setting is stored as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    String,
+    Integer,
+    Boolean,
+}
+
+/// All the settings supported in the configuration file, in the order they're listed.
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "download-server-url",
+        env_var: "CRITICALUP_DOWNLOAD_SERVER_URL",
+        kind: Kind::String,
+        validate: is_url,
+    },
+    Setting {
+        key: "http-proxy",
+        env_var: "HTTPS_PROXY",
+        kind: Kind::String,
+        validate: is_url,
+    },
+    Setting {
+        key: "no-proxy",
+        env_var: "NO_PROXY",
+        kind: Kind::String,
+        validate: |_| true,
+    },
+    Setting {
+        key: "ca-certificates",
+        env_var: "CRITICALUP_CA_CERTIFICATES",
+        kind: Kind::String,
+        validate: parses::<PathBuf>,
+    },
+    Setting {
+        key: "client-certificate",
+        env_var: "CRITICALUP_CLIENT_CERTIFICATE",
+        kind: Kind::String,
+        validate: parses::<PathBuf>,
+    },
+    Setting {
+        key: "timeout",
+        env_var: "CRITICALUP_TIMEOUT",
+        kind: Kind::Integer,
+        validate: parses::<u64>,
+    },
+    Setting {
+        key: "lock-timeout",
+        env_var: "CRITICALUP_LOCK_TIMEOUT",
+        kind: Kind::Integer,
+        validate: parses::<u64>,
+    },
+    Setting {
+        key: "retries",
+        env_var: "CRITICALUP_RETRIES",
+        kind: Kind::Integer,
+        validate: parses::<u32>,
+    },
+    Setting {
+        key: "jobs",
+        env_var: "CRITICALUP_JOBS",
+        kind: Kind::Integer,
+        validate: parses::<NonZeroUsize>,
+    },
+    Setting {
+        key: "cache-dir",
+        env_var: "CRITICALUP_CACHE_DIR",
+        kind: Kind::String,
+        validate: parses::<PathBuf>,
+    },
+    Setting {
+        key: "cache-max-size",
+        env_var: "CRITICALUP_CACHE_MAX_SIZE",
+        kind: Kind::String,
+        validate: parses::<ByteSize>,
+    },
+    Setting {
+        key: "verify-binaries",
+        env_var: "CRITICALUP_VERIFY_BINARIES",
+        kind: Kind::Boolean,
+        validate: parses::<bool>,
+    },
+    Setting {
+        key: "format",
+        env_var: "CRITICALUP_FORMAT",
+        kind: Kind::String,
+        validate: |value| matches!(value, "text" | "json"),
+    },
+];
+
+impl Setting {
+    /// Find the setting named `key`.
+    pub fn find(key: &str) -> Result<&'static Setting, Error> {
+        SETTINGS
+            .iter()
+            .find(|setting| setting.key == key)
+            .ok_or_else(|| Error::UnknownSetting(key.into()))
+    }
+
+    /// Check whether `value` is valid for the setting.
+    pub fn is_valid(&self, value: &str) -> bool {
+        (self.validate)(value)
+    }
+
+    /// Value of the environment variable overriding the setting, if it's set.
+    pub fn env_value(&self) -> Option<String> {
+        std::env::var_os(self.env_var)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string_lossy().into())
+    }
+}
+
+fn is_url(value: &str) -> bool {
+    reqwest::Url::parse(value).is_ok()
+}
+
+fn parses<T: std::str::FromStr>(value: &str) -> bool {
+    value.parse::<T>().is_ok()
+}
+
+/// Contents of the configuration file.
+pub struct ConfigFile {
+    path: PathBuf,
+    document: Document,
+}
+
+impl ConfigFile {
+    /// Load the configuration file at `path`, which is treated as empty if it doesn't exist.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(Error::CantReadConfigFile(path.into(), err)),
+        };
+        let document: Document = contents
+            .parse()
+            .map_err(|e| Error::CorruptConfigFile(path.into(), e))?;
+
+        let file = ConfigFile {
+            path: path.into(),
+            document,
+        };
+        // Reject unknown or invalid settings early, rather than ignoring a typo.
+        for (key, _) in file.document.iter() {
+            file.get(Setting::find(key)?)?;
+        }
+        Ok(file)
+    }
+
+    /// Path of the configuration file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Value of `setting` in the configuration file, if it's set.
+    pub fn get(&self, setting: &Setting) -> Result<Option<String>, Error> {
+        let Some(item) = self.document.get(setting.key) else {
+            return Ok(None);
+        };
+        let raw = match setting.kind {
+            Kind::String => item.as_str().map(String::from),
+            Kind::Integer => item.as_integer().map(|v| v.to_string()),
+            Kind::Boolean => item.as_bool().map(|v| v.to_string()),
+        };
+        match raw {
+            Some(raw) if setting.is_valid(&raw) => Ok(Some(raw)),
+            _ => Err(Error::InvalidConfigValue {
+                path: self.path.clone(),
+                key: setting.key,
+                value: item.to_string().trim().into(),
+            }),
+        }
+    }
+
+    /// Change the value of `setting`, removing it from the file when `raw` is empty. Changes are
+    /// only written to disk by [`ConfigFile::persist`].
+    pub fn set(&mut self, setting: &Setting, raw: &str) -> Result<(), Error> {
+        if raw.is_empty() {
+            self.document.remove(setting.key);
+            return Ok(());
+        }
+
+        let invalid = || Error::InvalidConfigValue {
+            path: self.path.clone(),
+            key: setting.key,
+            value: raw.into(),
+        };
+        if !setting.is_valid(raw) {
+            return Err(invalid());
+        }
+        let item: Item = match setting.kind {
+            Kind::String => value(raw),
+            Kind::Integer => value(raw.parse::<i64>().map_err(|_| invalid())?),
+            Kind::Boolean => value(raw.parse::<bool>().map_err(|_| invalid())?),
+        };
+        self.document[setting.key] = item;
+        Ok(())
+    }
+
+    /// Write the configuration file to disk.
+    pub fn persist(&self) -> Result<(), Error> {
+        write_file_atomically(&self.path, self.document.to_string().as_bytes())
+            .map_err(|e| Error::CantWriteConfigFile(self.path.clone(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestEnvironment;
+
+    #[test]
+    fn test_missing_file_is_empty() {
+        let test_env = TestEnvironment::prepare();
+        let file = ConfigFile::load(&test_env.root().join("config.toml")).unwrap();
+        for setting in SETTINGS {
+            assert_eq!(None, file.get(setting).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let test_env = TestEnvironment::prepare();
+        let path = test_env.root().join("config.toml");
+        std::fs::write(&path, "# Managed by hand.\njobs = 2\n").unwrap();
+
+        let mut file = ConfigFile::load(&path).unwrap();
+        let jobs = Setting::find("jobs").unwrap();
+        let format = Setting::find("format").unwrap();
+        assert_eq!(Some("2".into()), file.get(jobs).unwrap());
+
+        file.set(jobs, "8").unwrap();
+        file.set(format, "json").unwrap();
+        file.set(Setting::find("verify-binaries").unwrap(), "true")
+            .unwrap();
+        file.persist().unwrap();
+
+        assert_eq!(
+            "# Managed by hand.\njobs = 8\nformat = \"json\"\nverify-binaries = true\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        let mut file = ConfigFile::load(&path).unwrap();
+        assert_eq!(Some("8".into()), file.get(jobs).unwrap());
+        assert_eq!(Some("json".into()), file.get(format).unwrap());
+
+        file.set(format, "").unwrap();
+        assert_eq!(None, file.get(format).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_values_are_rejected() {
+        let test_env = TestEnvironment::prepare();
+        let path = test_env.root().join("config.toml");
+        let mut file = ConfigFile::load(&path).unwrap();
+
+        for (key, invalid) in [
+            ("jobs", "0"),
+            ("retries", "-1"),
+            ("format", "yaml"),
+            ("http-proxy", "not a url"),
+            ("cache-max-size", "ten gigabytes"),
+        ] {
+            assert!(matches!(
+                file.set(Setting::find(key).unwrap(), invalid),
+                Err(Error::InvalidConfigValue { key: k, value, .. }) if k == key && value == invalid
+            ));
+        }
+        assert!(matches!(
+            Setting::find("job"),
+            Err(Error::UnknownSetting(key)) if key == "job"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_files_are_rejected() {
+        let test_env = TestEnvironment::prepare();
+        let path = test_env.root().join("config.toml");
+
+        std::fs::write(&path, "jobs = \"four\"\n").unwrap();
+        assert!(matches!(
+            ConfigFile::load(&path),
+            Err(Error::InvalidConfigValue { key: "jobs", value, .. }) if value == "\"four\""
+        ));
+
+        std::fs::write(&path, "job = 4\n").unwrap();
+        assert!(matches!(
+            ConfigFile::load(&path),
+            Err(Error::UnknownSetting(key)) if key == "job"
+        ));
+
+        std::fs::write(&path, "jobs = \n").unwrap();
+        assert!(matches!(
+            ConfigFile::load(&path),
+            Err(Error::CorruptConfigFile(..))
+        ));
+    }
+}
@@ -7,13 +7,18 @@ use std::env;
 use std::path::{Path, PathBuf};
 
 const DEFAULT_INSTALLATION_DIR_NAME: &str = "toolchains";
+const DEFAULT_CACHE_DIR_NAME: &str = "cache";
+const DEFAULT_LOCKS_DIR_NAME: &str = "locks";
 
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub struct Paths {
     pub(crate) state_file: PathBuf,
 
+    pub config_file: PathBuf,
     pub proxies_dir: PathBuf,
     pub installation_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub locks_dir: PathBuf,
 
     #[cfg(test)]
     pub(crate) root: PathBuf,
@@ -36,8 +41,11 @@ impl Paths {
 
         Ok(Paths {
             state_file: root.join("state.json"),
+            config_file: root.join("config.toml"),
             proxies_dir: root.join("bin"),
             installation_dir: root.join(DEFAULT_INSTALLATION_DIR_NAME),
+            cache_dir: root.join(DEFAULT_CACHE_DIR_NAME),
+            locks_dir: root.join(DEFAULT_LOCKS_DIR_NAME),
             #[cfg(test)]
             root,
         })
@@ -70,8 +78,11 @@ mod tests {
         assert_eq!(
             Paths {
                 state_file: "/opt/criticalup/state.json".into(),
+                config_file: "/opt/criticalup/config.toml".into(),
                 proxies_dir: "/opt/criticalup/bin".into(),
                 installation_dir: "/opt/criticalup/toolchains".into(),
+                cache_dir: "/opt/criticalup/cache".into(),
+                locks_dir: "/opt/criticalup/locks".into(),
                 root: "/opt/criticalup".into()
             },
             Paths::detect(&WhitelabelConfig::test(), Some("/opt/criticalup".into()),).unwrap()
@@ -5,6 +5,7 @@ use criticaltrust::Error as TrustError;
 use reqwest::Error as ReqError;
 use reqwest::StatusCode;
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// We're using a custom error enum instead of `Box<dyn Error>` or one of the crates providing a
 /// `Box<dyn Error>` wrapper because we need to know all the possible errors criticalup could
@@ -14,6 +15,35 @@ use std::path::PathBuf;
 pub enum Error {
     #[error("could not detect the criticalup root directory")]
     CouldNotDetectRootDirectory,
+    #[error("invalid value for the {name} environment variable: {value:?}")]
+    InvalidEnvironmentVariable { name: &'static str, value: String },
+
+    #[error("failed to read the criticalup configuration file at {}", .0.display())]
+    CantReadConfigFile(PathBuf, #[source] std::io::Error),
+    #[error("failed to write the criticalup configuration file to {}", .0.display())]
+    CantWriteConfigFile(PathBuf, #[source] WriteFileError),
+    #[error("failed to parse the criticalup configuration file at {}", .0.display())]
+    CorruptConfigFile(PathBuf, #[source] toml_edit::TomlError),
+    #[error("unknown setting `{0}`")]
+    UnknownSetting(String),
+    #[error("invalid value for the `{key}` setting in {}: {value}", .path.display())]
+    InvalidConfigValue {
+        path: PathBuf,
+        key: &'static str,
+        value: String,
+    },
+    #[error("failed to read the CA certificates at {}", .0.display())]
+    CantReadCaCertificates(PathBuf, #[source] std::io::Error),
+    #[error("no valid PEM certificates found in {}", .0.display())]
+    InvalidCaCertificates(PathBuf),
+    #[error("failed to read the client certificate at {}", .0.display())]
+    CantReadClientCertificate(PathBuf, #[source] std::io::Error),
+    #[error("invalid client certificate at {}, a PEM certificate and private key are expected", .0.display())]
+    InvalidClientCertificate(PathBuf, #[source] ReqError),
+    #[error("invalid HTTP proxy {0}")]
+    InvalidHttpProxy(String, #[source] ReqError),
+    #[error("failed to configure the HTTP client")]
+    CantConfigureHttpClient(#[source] ReqError),
 
     #[error("failed to download {url}")]
     DownloadServerError {
@@ -22,6 +52,25 @@ pub enum Error {
         kind: DownloadServerError,
     },
 
+    #[error("the downloaded archive of package {package} does not match the release manifest")]
+    ArtifactVerificationFailed {
+        package: String,
+        #[source]
+        kind: ArtifactVerificationError,
+    },
+    #[error("failed to write the downloaded archive to {}", .0.display())]
+    CantWriteDownload(PathBuf, #[source] WriteFileError),
+
+    #[error("failed to access {} in the download cache", .0.display())]
+    CacheAccessFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("failed to read {} from the offline directory", .0.display())]
+    CantReadOfflineFile(PathBuf, #[source] std::io::Error),
+    #[error("failed to parse {} from the offline directory, is it corrupt?", .0.display())]
+    CorruptOfflineFile(PathBuf, #[source] serde_json::Error),
+    #[error("failed to write {} to the offline directory", .0.display())]
+    CantWriteOfflineFile(PathBuf, #[source] WriteFileError),
+
     #[error("state file at {} is not supported by this release (state format version {1})", .0.display())]
     UnsupportedStateFileVersion(PathBuf, u32),
     #[error("failed to read the criticalup state file at {}", .0.display())]
@@ -30,6 +79,31 @@ pub enum Error {
     CantWriteStateFile(PathBuf, #[source] WriteFileError),
     #[error("failed to parse the criticalup state file at {}, is it corrupt?", .0.display())]
     CorruptStateFile(PathBuf, #[source] serde_json::Error),
+    #[error(
+        "failed to upgrade the criticalup state file at {} from state format version {from}: {reason}",
+        .path.display()
+    )]
+    StateFileMigrationFailed {
+        path: PathBuf,
+        from: u32,
+        reason: String,
+    },
+    #[error("failed to back up the criticalup state file to {}", .0.display())]
+    CantBackUpStateFile(PathBuf, #[source] WriteFileError),
+
+    #[error("failed to acquire the lock at {}", .0.display())]
+    CantAcquireLock(PathBuf, #[source] std::io::Error),
+    #[error(
+        "timed out after {}s waiting for another criticalup process to release {what} \
+        (locked through {})",
+        .timeout.as_secs(),
+        .path.display()
+    )]
+    LockTimeout {
+        what: String,
+        path: PathBuf,
+        timeout: Duration,
+    },
 
     #[error("could not find a project manifest in the current or parent directories")]
     ProjectManifestDetectionFailed,
@@ -97,6 +171,18 @@ pub enum DownloadServerError {
     UnexpectedResponseData(#[source] serde_json::Error),
     #[error("failed to send the network request")]
     Network(#[source] reqwest::Error),
+    #[error("the download was interrupted")]
+    Interrupted(#[source] std::io::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArtifactVerificationError {
+    #[error("expected a size of {expected} bytes, but more bytes were downloaded")]
+    TooLarge { expected: usize },
+    #[error("expected a size of {expected} bytes, but only {found} bytes were downloaded")]
+    TooSmall { expected: usize, found: usize },
+    #[error("the checksum is wrong, the archive might have been tampered with")]
+    WrongChecksum,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -2,13 +2,18 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 pub mod binary_proxies;
+pub mod cache;
 pub mod config;
 pub mod download_server_client;
 pub mod errors;
+pub mod lock;
+pub mod offline;
+pub mod progress;
 pub mod project_manifest;
 
 pub mod state;
 
+mod retry;
 mod utils;
 
 #[cfg(test)]
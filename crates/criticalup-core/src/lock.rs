@@ -0,0 +1,269 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Advisory file locks coordinating multiple criticalup processes running at the same time, for
+//! example parallel CI jobs on the same machine.
+//!
+//! The state file is locked for each read-modify-write cycle (see [`State::update`]), while
+//! installations are locked for the whole time they're installed or removed. The download cache
+//! is locked in shared mode while archives are downloaded and read, and exclusively while they're
+//! removed from it. Locks are released when the [`FileLock`] is dropped, or when the process
+//! holding it exits.
+//!
+//! [`State::update`]: crate::state::State::update
+
+use crate::config::Config;
+use crate::errors::Error;
+use crate::project_manifest::InstallationId;
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often to check whether the lock was released while waiting for it.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Lock on a file, released when dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+/// Whether other processes can hold the same lock at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Exclusive,
+    Shared,
+}
+
+impl FileLock {
+    /// Lock the state file, so that only one process at a time changes it.
+    pub fn state(config: &Config) -> Result<Self, Error> {
+        Self::acquire(
+            &config.paths.locks_dir.join("state.lock"),
+            "the state file",
+            config.lock_timeout,
+            Mode::Exclusive,
+        )
+    }
+
+    /// Lock an installation, so that only one process at a time installs or removes it.
+    pub fn installation(config: &Config, installation_id: &InstallationId) -> Result<Self, Error> {
+        Self::acquire(
+            &installation_lock_path(config, installation_id),
+            &format!("installation {}", installation_id.0),
+            config.lock_timeout,
+            Mode::Exclusive,
+        )
+    }
+
+    /// Lock an installation only if no other process holds its lock, without waiting for it to
+    /// be released. Returns `None` when the installation is in use.
+    pub fn try_installation(
+        config: &Config,
+        installation_id: &InstallationId,
+    ) -> Result<Option<Self>, Error> {
+        match Self::acquire(
+            &installation_lock_path(config, installation_id),
+            &format!("installation {}", installation_id.0),
+            Duration::ZERO,
+            Mode::Exclusive,
+        ) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(Error::LockTimeout { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Lock the download cache in shared mode, so that no other process removes archives from it
+    /// while they're being downloaded or read. Multiple processes can hold the shared lock.
+    pub fn cache_shared(config: &Config) -> Result<Self, Error> {
+        Self::acquire(
+            &cache_lock_path(config),
+            "the download cache",
+            config.lock_timeout,
+            Mode::Shared,
+        )
+    }
+
+    /// Lock the download cache exclusively, so that archives can be removed from it.
+    pub fn cache(config: &Config) -> Result<Self, Error> {
+        Self::acquire(
+            &cache_lock_path(config),
+            "the download cache",
+            config.lock_timeout,
+            Mode::Exclusive,
+        )
+    }
+
+    /// Lock the download cache exclusively only if no other process is using it, without waiting
+    /// for it to be released. Returns `None` when the cache is in use.
+    pub fn try_cache(config: &Config) -> Result<Option<Self>, Error> {
+        match Self::acquire(
+            &cache_lock_path(config),
+            "the download cache",
+            Duration::ZERO,
+            Mode::Exclusive,
+        ) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(Error::LockTimeout { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Lock the download of the archive named `name`, so that only one process at a time writes
+    /// to its partial download.
+    pub(crate) fn download(locks_dir: &Path, name: &str, timeout: Duration) -> Result<Self, Error> {
+        Self::acquire(
+            &locks_dir.join(format!("download-{name}.lock")),
+            &format!("the download of {name}"),
+            timeout,
+            Mode::Exclusive,
+        )
+    }
+
+    /// Lock the file at `path`, waiting up to `timeout` for other processes to release it.
+    /// `what` describes what the lock protects in error messages.
+    fn acquire(path: &Path, what: &str, timeout: Duration, mode: Mode) -> Result<Self, Error> {
+        let err = |e| Error::CantAcquireLock(PathBuf::from(path), e);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(err)?;
+        }
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(err)?;
+
+        let start = Instant::now();
+        let mut warned = false;
+        loop {
+            let locked = match mode {
+                Mode::Exclusive => file.try_lock(),
+                Mode::Shared => file.try_lock_shared(),
+            };
+            match locked {
+                Ok(()) => {
+                    log::debug!(
+                        "locked {what} after waiting {:.1?} (through {})",
+                        start.elapsed(),
+                        path.display()
+                    );
+                    return Ok(FileLock { _file: file });
+                }
+                Err(TryLockError::Error(e)) => return Err(err(e)),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            if start.elapsed() >= timeout {
+                return Err(Error::LockTimeout {
+                    what: what.into(),
+                    path: path.into(),
+                    timeout,
+                });
+            }
+            if !warned {
+                log::warn!("waiting for another criticalup process to release {what}");
+                warned = true;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn installation_lock_path(config: &Config, installation_id: &InstallationId) -> PathBuf {
+    config
+        .paths
+        .locks_dir
+        .join(format!("installation-{}.lock", installation_id.0))
+}
+
+fn cache_lock_path(config: &Config) -> PathBuf {
+    config.paths.locks_dir.join("cache.lock")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestEnvironment;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let test_env = TestEnvironment::prepare();
+        let path = test_env.root().join("locks/sample.lock");
+        let timeout = Duration::from_millis(200);
+
+        let lock = FileLock::acquire(&path, "sample", timeout, Mode::Exclusive).unwrap();
+        match FileLock::acquire(&path, "sample", timeout, Mode::Exclusive) {
+            Err(Error::LockTimeout {
+                what,
+                path: locked,
+                timeout: waited,
+            }) => {
+                assert_eq!("sample", what);
+                assert_eq!(path, locked);
+                assert_eq!(timeout, waited);
+            }
+            other => panic!("unexpected result when acquiring the lock: {other:?}"),
+        }
+
+        // Other locks are independent.
+        FileLock::acquire(
+            &test_env.root().join("locks/other.lock"),
+            "other",
+            timeout,
+            Mode::Exclusive,
+        )
+        .unwrap();
+
+        drop(lock);
+        FileLock::acquire(&path, "sample", timeout, Mode::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_lock_waits_for_release() {
+        let test_env = TestEnvironment::prepare();
+        let path = test_env.root().join("locks/sample.lock");
+
+        let lock = FileLock::acquire(&path, "sample", Duration::ZERO, Mode::Exclusive).unwrap();
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            drop(lock);
+        });
+        FileLock::acquire(&path, "sample", Duration::from_secs(10), Mode::Exclusive).unwrap();
+        release.join().unwrap();
+    }
+
+    #[test]
+    fn test_try_installation() {
+        let test_env = TestEnvironment::prepare();
+        let id = InstallationId("sample".into());
+
+        let lock = FileLock::try_installation(test_env.config(), &id).unwrap();
+        assert!(lock.is_some());
+        assert!(FileLock::try_installation(test_env.config(), &id)
+            .unwrap()
+            .is_none());
+        drop(lock);
+        assert!(FileLock::try_installation(test_env.config(), &id)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_cache_lock_modes() {
+        let test_env = TestEnvironment::prepare();
+        let config = test_env.config();
+
+        // Multiple processes can read from the cache at the same time, but archives can't be
+        // removed while it's being read.
+        let shared = FileLock::cache_shared(config).unwrap();
+        let other_shared = FileLock::cache_shared(config).unwrap();
+        assert!(FileLock::try_cache(config).unwrap().is_none());
+
+        drop(shared);
+        drop(other_shared);
+        let exclusive = FileLock::try_cache(config).unwrap();
+        assert!(exclusive.is_some());
+        assert!(FileLock::try_cache(config).unwrap().is_none());
+    }
+}
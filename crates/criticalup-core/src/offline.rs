@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Directories containing the artifacts of the download server, used to install without network
+//! access (for example in air-gapped environments).
+//!
+//! Files are laid out like the paths of the download server API. As the paths of the keys and of
+//! the release manifests would clash with the directories containing the package archives, JSON
+//! files have a `.json` extension added to them:
+//!
+//! ```text
+//! v1/keys.json
+//! v1/releases/{product}/{release}.json
+//! v1/releases/{product}/{release}/download/{package}/{format}
+//! ```
+//!
+//! Nothing in the directory is trusted: everything is verified exactly like it would be when
+//! downloaded from the download server.
+
+use crate::errors::{Error, WriteFileError};
+use crate::utils::write_file_atomically;
+use criticaltrust::manifests::ReleaseArtifactFormat;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct OfflineDir {
+    root: PathBuf,
+}
+
+impl OfflineDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        OfflineDir { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn keys_manifest(&self) -> PathBuf {
+        self.root.join("v1").join("keys.json")
+    }
+
+    pub fn release_manifest(&self, product: &str, release: &str) -> PathBuf {
+        self.releases_dir(product).join(format!("{release}.json"))
+    }
+
+    pub fn package_archive(
+        &self,
+        product: &str,
+        release: &str,
+        package: &str,
+        format: &ReleaseArtifactFormat,
+    ) -> PathBuf {
+        self.releases_dir(product)
+            .join(release)
+            .join("download")
+            .join(package)
+            .join(format.to_string())
+    }
+
+    pub(crate) fn read_json<T: for<'de> Deserialize<'de>>(&self, path: &Path) -> Result<T, Error> {
+        let contents =
+            std::fs::read(path).map_err(|e| Error::CantReadOfflineFile(path.into(), e))?;
+        serde_json::from_slice(&contents).map_err(|e| Error::CorruptOfflineFile(path.into(), e))
+    }
+
+    /// Write a JSON file (like the keys manifest or a release manifest) to `path`, which must be
+    /// inside the offline directory.
+    pub fn write_json<T: Serialize>(&self, path: &Path, value: &T) -> Result<(), Error> {
+        let err = |e| Error::CantWriteOfflineFile(path.into(), e);
+        let serialized =
+            serde_json::to_vec_pretty(value).map_err(|e| err(WriteFileError::Io(e.into())))?;
+        write_file_atomically(path, &serialized).map_err(err)
+    }
+
+    fn releases_dir(&self, product: &str) -> PathBuf {
+        self.root.join("v1").join("releases").join(product)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        let dir = OfflineDir::new("/mirror");
+        assert_eq!(Path::new("/mirror/v1/keys.json"), dir.keys_manifest());
+        assert_eq!(
+            Path::new("/mirror/v1/releases/ferrocene/stable-1.0.0.json"),
+            dir.release_manifest("ferrocene", "stable-1.0.0")
+        );
+        assert_eq!(
+            Path::new("/mirror/v1/releases/ferrocene/stable-1.0.0/download/rustc/tar.zst"),
+            dir.package_archive(
+                "ferrocene",
+                "stable-1.0.0",
+                "rustc",
+                &ReleaseArtifactFormat::TarZst
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_json() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = OfflineDir::new(root.path());
+        let path = root.path().join("file.json");
+
+        assert!(matches!(
+            dir.read_json::<Vec<u32>>(&path),
+            Err(Error::CantReadOfflineFile(p, _)) if p == path
+        ));
+
+        std::fs::write(&path, b"[1, 2").unwrap();
+        assert!(matches!(
+            dir.read_json::<Vec<u32>>(&path),
+            Err(Error::CorruptOfflineFile(p, _)) if p == path
+        ));
+
+        std::fs::write(&path, b"[1, 2]").unwrap();
+        assert_eq!(vec![1, 2], dir.read_json::<Vec<u32>>(&path).unwrap());
+    }
+
+    #[test]
+    fn test_write_json() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = OfflineDir::new(root.path());
+
+        dir.write_json(&dir.keys_manifest(), &vec![1, 2]).unwrap();
+        assert_eq!(
+            vec![1, 2],
+            dir.read_json::<Vec<u32>>(&dir.keys_manifest()).unwrap()
+        );
+    }
+}
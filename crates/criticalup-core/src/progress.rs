@@ -0,0 +1,116 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Progress reporting for long running operations, like downloading and unpacking packages.
+//!
+//! criticalup-core doesn't render progress on its own: callers implement [`ProgressReporter`] and
+//! display the progress however they see fit (for example with progress bars, or not at all).
+
+use std::io::Read;
+
+/// Step of the installation of a package that progress is being reported for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ProgressStep {
+    /// Downloading the archive of the package, verifying it against the release manifest.
+    Download,
+    /// Unpacking the archive of the package, verifying the integrity of each file in it.
+    Unpack,
+}
+
+/// Receiver of progress updates. Updates can be sent from multiple threads at the same time, as
+/// packages are processed concurrently.
+///
+/// All methods do nothing by default, so implementors only need to handle what they care about.
+pub trait ProgressReporter: Send + Sync {
+    /// A step started for `package`, which will process `total` bytes. It's called again for the
+    /// same step and package when the step is restarted (for example when a download is retried),
+    /// in which case progress should be reset.
+    fn start(&self, step: ProgressStep, package: &str, total: u64) {
+        let _ = (step, package, total);
+    }
+
+    /// Another `bytes` bytes were processed by the step.
+    fn advance(&self, step: ProgressStep, package: &str, bytes: u64) {
+        let _ = (step, package, bytes);
+    }
+
+    /// The step completed successfully. Steps that fail are never finished.
+    fn finish(&self, step: ProgressStep, package: &str) {
+        let _ = (step, package);
+    }
+}
+
+/// Reporter discarding all progress updates.
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {}
+
+/// Reader reporting how many bytes were read from it as progress of a step.
+pub struct ProgressReader<'a, R> {
+    reader: R,
+    reporter: &'a dyn ProgressReporter,
+    step: ProgressStep,
+    package: &'a str,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(
+        reader: R,
+        reporter: &'a dyn ProgressReporter,
+        step: ProgressStep,
+        package: &'a str,
+    ) -> Self {
+        Self {
+            reader,
+            reporter,
+            step,
+            package,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        if len > 0 {
+            self.reporter.advance(self.step, self.package, len as u64);
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(ProgressStep, String, u64)>>);
+
+    impl ProgressReporter for Recorder {
+        fn advance(&self, step: ProgressStep, package: &str, bytes: u64) {
+            self.0.lock().unwrap().push((step, package.into(), bytes));
+        }
+    }
+
+    #[test]
+    fn test_progress_reader() {
+        let recorder = Recorder::default();
+        let data = vec![42; 1000];
+        let mut reader =
+            ProgressReader::new(data.as_slice(), &recorder, ProgressStep::Unpack, "rustc");
+
+        let mut buf = [0; 300];
+        while reader.read(&mut buf).unwrap() > 0 {}
+
+        let updates = recorder.0.into_inner().unwrap();
+        assert_eq!(
+            vec![300, 300, 300, 100],
+            updates.iter().map(|u| u.2).collect::<Vec<_>>()
+        );
+        assert!(updates
+            .iter()
+            .all(|u| u.0 == ProgressStep::Unpack && u.1 == "rustc"));
+    }
+}
@@ -21,6 +21,7 @@ const DEFAULT_PROJECT_MANIFEST_VERSION: u32 = 1;
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct ProjectManifest {
     products: Vec<ProjectManifestProduct>,
+    verify_binaries: bool,
 }
 
 impl ProjectManifest {
@@ -99,13 +100,19 @@ impl ProjectManifest {
         &self.products
     }
 
+    /// Whether binary proxies must check binaries against the checksums recorded when they were
+    /// installed before executing them.
+    pub fn verify_binaries(&self) -> bool {
+        self.verify_binaries
+    }
+
     /// Generates a directory for each product under the specified `root`.
     ///
     /// If the directory already exists, then just skips the creation.
     pub fn create_products_dirs(&self, installation_dir: &Path) -> std::io::Result<()> {
         let products = self.products();
         for product in products {
-            std::fs::create_dir_all(installation_dir.join(&product.installation_id()))?;
+            std::fs::create_dir_all(installation_dir.join(product.installation_id()))?;
         }
 
         Ok(())
@@ -220,6 +227,7 @@ struct VersionDetector {
 
 fn load_inner(path: &Path) -> Result<ProjectManifest, ProjectManifestLoadingError> {
     let mut products = Vec::new();
+    let verify_binaries;
 
     let contents = std::fs::read(path).map_err(ProjectManifestLoadingError::FailedToRead)?;
 
@@ -231,6 +239,7 @@ fn load_inner(path: &Path) -> Result<ProjectManifest, ProjectManifestLoadingErro
         DEFAULT_PROJECT_MANIFEST_VERSION => {
             let manifest: v1::ProjectManifest = toml_edit::de::from_slice(&contents)
                 .map_err(ProjectManifestLoadingError::FailedToParse)?;
+            verify_binaries = manifest.verify_binaries;
 
             for (name, product) in manifest.products.into_iter() {
                 let mut packages = Packages(
@@ -270,7 +279,10 @@ fn load_inner(path: &Path) -> Result<ProjectManifest, ProjectManifestLoadingErro
         ));
     }
 
-    Ok(ProjectManifest { products })
+    Ok(ProjectManifest {
+        products,
+        verify_binaries,
+    })
 }
 
 #[cfg(test)]
@@ -405,7 +417,8 @@ mod tests {
                         name: "sample".into(),
                         release: "foo".into(),
                         packages: Packages(vec!["bar".into()]),
-                    }]
+                    }],
+                    verify_binaries: false,
                 },
                 manifest
             );
@@ -470,6 +483,7 @@ mod tests {
                 "manifest-version = 1",
                 ProjectManifest {
                     products: Vec::new(),
+                    verify_binaries: false,
                 },
             );
         }
@@ -490,6 +504,29 @@ mod tests {
                         release: "foo".into(),
                         packages: Packages(vec!["bar".into(), "baz".into()]),
                     }],
+                    verify_binaries: false,
+                },
+            );
+        }
+
+        #[test]
+        fn test_v1_verify_binaries() {
+            assert_load(
+                r#"
+                    manifest-version = 1
+                    verify-binaries = true
+
+                    [products.sample]
+                    release = "foo"
+                    packages = ["bar"]
+                "#,
+                ProjectManifest {
+                    products: vec![ProjectManifestProduct {
+                        name: "sample".into(),
+                        release: "foo".into(),
+                        packages: Packages(vec!["bar".into()]),
+                    }],
+                    verify_binaries: true,
                 },
             );
         }
@@ -523,6 +560,7 @@ mod tests {
                             packages: Packages(vec!["bar".into(), "baz".into()]),
                         },
                     ],
+                    verify_binaries: false,
                 },
             );
         }
@@ -565,6 +603,7 @@ mod tests {
                         release: env!("TARGET").into(),
                         packages: Packages(vec![concat!("foo-", env!("TARGET")).into()]),
                     }],
+                    verify_binaries: false,
                 },
             );
         }
@@ -724,6 +763,7 @@ mod tests {
 
             let test_manifest = crate::project_manifest::ProjectManifest {
                 products: vec![product1, product2],
+                verify_binaries: false,
             };
 
             // Main project dir is created along with product dirs.
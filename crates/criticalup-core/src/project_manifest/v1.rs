@@ -11,6 +11,8 @@ pub(super) struct ProjectManifest {
     manifest_version: u32,
     #[serde(default)]
     pub(super) products: HashMap<String, ProjectManifestProduct>,
+    #[serde(default)]
+    pub(super) verify_binaries: bool,
 }
 
 #[derive(Deserialize)]
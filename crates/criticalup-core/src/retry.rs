@@ -0,0 +1,139 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How failed requests to the download server are retried.
+///
+/// Retries use an exponential backoff with jitter, to avoid all clients hitting the server at the
+/// same time after an outage. When the server tells us how long to wait (with the `Retry-After`
+/// header) that is honoured instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_retries: u32,
+    pub(crate) base_delay: Duration,
+    pub(crate) max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub(crate) fn new(max_retries: u32, test_mode: bool) -> Self {
+        if test_mode {
+            // Don't slow the test suite down with real delays.
+            RetryPolicy {
+                max_retries,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_secs(2),
+            }
+        } else {
+            RetryPolicy {
+                max_retries,
+                base_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(60),
+            }
+        }
+    }
+
+    /// Return how long to wait before the retry number `attempt` (starting from zero), or `None`
+    /// if the request should not be retried anymore.
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        self.delay_with_jitter(attempt, retry_after, random_fraction())
+    }
+
+    fn delay_with_jitter(
+        &self,
+        attempt: u32,
+        retry_after: Option<Duration>,
+        jitter: f64,
+    ) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        if let Some(retry_after) = retry_after {
+            // Waiting for longer than the maximum delay would look like criticalup is stuck, so
+            // give up instead.
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        // Wait at least half of the exponential delay, adding a random amount on top of it.
+        Some(exponential / 2 + exponential.div_f64(2.0).mul_f64(jitter))
+    }
+}
+
+/// Random number between 0 and 1. The standard library has no random number generator, but the
+/// keys of `RandomState` are randomly generated, which is good enough for jitter.
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random % 1_000_000) as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 8,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let delay = |attempt, jitter| {
+            policy()
+                .delay_with_jitter(attempt, None, jitter)
+                .map(|d| d.as_millis())
+        };
+
+        assert_eq!(Some(500), delay(0, 0.0));
+        assert_eq!(Some(1000), delay(0, 1.0));
+        assert_eq!(Some(1000), delay(1, 0.0));
+        assert_eq!(Some(1500), delay(1, 0.5));
+        assert_eq!(Some(4000), delay(3, 0.0));
+        assert_eq!(Some(8000), delay(3, 1.0));
+        // Capped to the maximum delay.
+        assert_eq!(Some(30_000), delay(7, 0.0));
+        assert_eq!(Some(60_000), delay(7, 1.0));
+        // No more retries.
+        assert_eq!(None, delay(8, 0.0));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let policy = policy();
+        let delay = |attempt, secs| policy.delay_with_jitter(attempt, Some(secs), 0.5);
+
+        assert_eq!(Some(Duration::ZERO), delay(0, Duration::ZERO));
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            delay(0, Duration::from_secs(10))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(60)),
+            delay(5, Duration::from_secs(60))
+        );
+        assert_eq!(None, delay(0, Duration::from_secs(61)));
+        assert_eq!(None, delay(8, Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_no_retries() {
+        let policy = RetryPolicy::new(0, false);
+        assert_eq!(None, policy.delay(0, None));
+        assert_eq!(None, policy.delay(0, Some(Duration::ZERO)));
+    }
+
+    #[test]
+    fn test_random_fraction() {
+        for _ in 0..100 {
+            assert!((0.0..1.0).contains(&random_fraction()));
+        }
+    }
+}
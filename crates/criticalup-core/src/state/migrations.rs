@@ -0,0 +1,81 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Upgrades of state files written by older releases of criticalup.
+//!
+//! Each migration upgrades a state file by a single format version, so state files of any older
+//! version are upgraded step by step. Migrations operate on the JSON contents of the state file
+//! rather than on `StateRepr`, as older formats don't necessarily deserialize into the current
+//! one.
+
+use serde_json::Value;
+
+/// Migration upgrading a state file from the format version it's listed with to the next one,
+/// returning why the state file couldn't be upgraded on failure.
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+const MIGRATIONS: &[(u32, Migration)] = &[(1, v1_to_v2)];
+
+/// Upgrade the contents of a state file from format version `from` to `to`.
+///
+/// Returns the version the migration failed at and why, or `None` as the reason if there is no
+/// migration from that version.
+pub(super) fn migrate(
+    contents: &mut Value,
+    from: u32,
+    to: u32,
+) -> Result<(), (u32, Option<String>)> {
+    for version in from..to {
+        let (_, migration) = MIGRATIONS
+            .iter()
+            .find(|(migrates_from, _)| *migrates_from == version)
+            .ok_or((version, None))?;
+        migration(contents).map_err(|reason| (version, Some(reason)))?;
+        contents["version"] = (version + 1).into();
+    }
+    Ok(())
+}
+
+/// Version 2 added metadata to installations. It's optional, as it can't be recovered for existing
+/// installations. Binary proxy checksums were already an optional field of version 1.
+fn v1_to_v2(_contents: &mut Value) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate_from_v1() {
+        let mut contents = json!({
+            "version": 1,
+            "authentication_token": null,
+            "installations": {
+                "sample": {
+                    "binary_proxies": { "rustc": "bin/rustc" },
+                    "manifests": [],
+                },
+            },
+        });
+        let mut expected = contents.clone();
+        expected["version"] = 2.into();
+
+        migrate(&mut contents, 1, 2).unwrap();
+        assert_eq!(expected, contents);
+    }
+
+    #[test]
+    fn test_migrate_without_migration() {
+        let mut contents = json!({ "version": 0 });
+        assert_eq!(Err((0, None)), migrate(&mut contents, 0, 2));
+    }
+
+    #[test]
+    fn test_migrate_to_same_version() {
+        let mut contents = json!({ "version": 2 });
+        migrate(&mut contents, 2, 2).unwrap();
+        assert_eq!(json!({ "version": 2 }), contents);
+    }
+}
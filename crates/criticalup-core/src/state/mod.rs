@@ -3,22 +3,25 @@
 
 use std::cell::{Ref, RefCell};
 use std::collections::{BTreeMap, BTreeSet};
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use serde::{Deserialize, Serialize};
 
 use criticaltrust::integrity::VerifiedPackage;
+use criticaltrust::manifests::ReleaseArtifact;
 
 use crate::config::Config;
 use crate::errors::Error;
 use crate::errors::Error::InstallationDoesNotExist;
-use crate::errors::WriteFileError;
+use crate::lock::FileLock;
 use crate::project_manifest::InstallationId;
-use crate::utils::open_file_for_write;
+use crate::utils::{hex_encode, write_file_atomically};
+use sha2::{Digest, Sha256};
 
-const CURRENT_FORMAT_VERSION: u32 = 1;
+mod migrations;
+
+const CURRENT_FORMAT_VERSION: u32 = 2;
 const CRITICALUP_TOKEN_ENV_VAR_NAME: &str = "CRITICALUP_TOKEN";
 
 #[derive(Clone)]
@@ -29,24 +32,38 @@ pub struct State {
 impl State {
     /// Construct the `State` object by loading the content from state file from disk.
     pub fn load(config: &Config) -> Result<Self, Error> {
-        let path = config.paths.state_file.clone();
-
-        let repr = match std::fs::read(&path) {
-            Ok(contents) => serde_json::from_slice(&contents)
-                .map_err(|e| Error::CorruptStateFile(path.clone(), e))?,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => StateRepr::default(),
-            Err(err) => return Err(Error::CantReadStateFile(path, err)),
-        };
-
-        if repr.version != CURRENT_FORMAT_VERSION {
-            return Err(Error::UnsupportedStateFileVersion(path, repr.version));
-        }
-
+        let inner = StateInner::read(config.paths.state_file.clone())?;
         Ok(State {
-            inner: Rc::new(RefCell::new(StateInner { path, repr })),
+            inner: Rc::new(RefCell::new(inner)),
         })
     }
 
+    /// Load the content of the state file from disk again, discarding changes that were not
+    /// persisted.
+    pub fn reload(&self) -> Result<(), Error> {
+        let path = self.inner.borrow().path.clone();
+        let inner = StateInner::read(path)?;
+        *self.inner.borrow_mut() = inner;
+        Ok(())
+    }
+
+    /// Change the state while no other criticalup process can change it.
+    ///
+    /// The state file is locked, loaded again to include the changes other processes made since
+    /// it was loaded, changed by `f` and persisted. Changes made before calling this method that
+    /// were not persisted are discarded.
+    pub fn update<T, E: From<Error>>(
+        &self,
+        config: &Config,
+        f: impl FnOnce(&State) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let _lock = FileLock::state(config)?;
+        self.reload()?;
+        let result = f(self)?;
+        self.persist()?;
+        Ok(result)
+    }
+
     /// Returns the authentication token.
     ///
     /// Attempts to read from:
@@ -130,39 +147,46 @@ impl State {
         let mut inner = self.inner.borrow_mut();
         let existing_installation_in_state_exists =
             inner.repr.installations.contains_key(installation_id);
-        let installation_path_on_disk_exists = config
-            .paths
-            .installation_dir
-            .join(&installation_id.0)
-            .exists();
+        let installation_path = config.paths.installation_dir.join(&installation_id.0);
+        let installation_path_on_disk_exists = installation_path.exists();
         match (
             existing_installation_in_state_exists,
             installation_path_on_disk_exists,
         ) {
             (true, true) => {
+                log::debug!(
+                    "updating installation {} in the state for manifest {}",
+                    installation_id.0,
+                    manifest.display()
+                );
                 inner.update_installation_manifests(installation_id, &manifest)?;
+
+                // The installation could have been installed again, so its binaries could have
+                // changed.
+                if let Some(installation) = inner.repr.installations.get_mut(installation_id) {
+                    installation.binary_proxy_digests = binary_proxy_digests(packages);
+                }
             }
 
             (false, _) => {
+                log::debug!(
+                    "adding installation {} to the state for manifest {}",
+                    installation_id.0,
+                    manifest.display()
+                );
                 inner.remove_manifest_from_all_installations(&manifest);
 
                 // Create the new installation for provided manifest.
                 let manifests = BTreeSet::from([manifest]);
                 inner.repr.installations.insert(
                     installation_id.clone(),
-                    StateInstallation {
-                        manifests,
-                        binary_proxies: packages
-                            .iter()
-                            .flat_map(|package| package.proxies_paths.iter())
-                            .map(|(k, v)| (k.clone(), v.into()))
-                            .collect(),
-                    },
+                    StateInstallation::new(packages, manifests),
                 );
             }
             (true, false) => {
-                eprintln!(
-                    "Installation in the State exists but the installation directory is missing."
+                log::warn!(
+                    "the directory of installation {} is missing, removing it from the state",
+                    installation_id.0
                 );
                 inner.repr.installations.remove(installation_id);
             }
@@ -199,6 +223,7 @@ impl State {
 
     /// Remove an installation from the `State` for a given `InstallationId`.
     pub fn remove_installation(&self, installation_id: &InstallationId) {
+        log::debug!("removing installation {} from the state", installation_id.0);
         self.inner
             .borrow_mut()
             .repr
@@ -206,6 +231,50 @@ impl State {
             .remove(installation_id);
     }
 
+    /// Record what the installation contains, replacing any metadata recorded before.
+    pub fn set_installation_metadata(
+        &self,
+        installation_id: &InstallationId,
+        metadata: InstallationMetadata,
+    ) -> Result<(), Error> {
+        match self
+            .inner
+            .borrow_mut()
+            .repr
+            .installations
+            .get_mut(installation_id)
+        {
+            Some(installation) => {
+                // The metadata lists the packages too, so they're not recorded twice.
+                installation.packages.clear();
+                installation.metadata = Some(metadata);
+                Ok(())
+            }
+            None => Err(InstallationDoesNotExist(installation_id.0.to_owned())),
+        }
+    }
+
+    /// Check whether the binary at `path` matches the checksum recorded for the `name` binary
+    /// proxy when the installation was created, returning `None` if no checksum was recorded.
+    pub fn binary_proxy_matches(
+        &self,
+        installation: &InstallationId,
+        name: &str,
+        path: &Path,
+    ) -> std::io::Result<Option<bool>> {
+        let expected = self
+            .inner
+            .borrow()
+            .repr
+            .installations
+            .get(installation)
+            .and_then(|i| i.binary_proxy_digests.get(name).cloned());
+        match expected {
+            Some(expected) => Ok(Some(sha256_file(path)? == expected)),
+            None => Ok(None),
+        }
+    }
+
     pub fn resolve_binary_proxy(
         &self,
         installation: &InstallationId,
@@ -221,7 +290,7 @@ impl State {
     }
 
     /// Gets all the installations listed in the `State` file.
-    pub fn installations(&self) -> Ref<BTreeMap<InstallationId, StateInstallation>> {
+    pub fn installations(&self) -> Ref<'_, BTreeMap<InstallationId, StateInstallation>> {
         Ref::map(self.inner.borrow(), |v| &v.repr.installations)
     }
 
@@ -242,7 +311,26 @@ impl State {
     }
 
     pub fn persist(&self) -> Result<(), Error> {
-        let inner = self.inner.borrow();
+        let mut inner = self.inner.borrow_mut();
+
+        // Migrated state files are only backed up before they're overwritten, so that commands
+        // not changing the state don't leave backups around.
+        if let Some(migrated_from) = inner.migrated_from.take() {
+            let backup = migration_backup_path(&inner.path, migrated_from.version);
+            write_file_atomically(&backup, &migrated_from.contents)
+                .map_err(|e| Error::CantBackUpStateFile(backup.clone(), e))?;
+        }
+
+        // The previous state file is kept as a backup to recover from if the state file is ever
+        // found corrupt. Corrupt state files are never backed up, so the backup is always the last
+        // state file that could be parsed.
+        if let Ok(previous) = std::fs::read(&inner.path) {
+            if serde_json::from_slice::<serde_json::Value>(&previous).is_ok() {
+                let backup = backup_path(&inner.path);
+                write_file_atomically(&backup, &previous)
+                    .map_err(|e| Error::CantBackUpStateFile(backup.clone(), e))?;
+            }
+        }
 
         // According to the serde_json documentation, the only two reasons this could fail is if
         // either the serialize implementation returns an error, or a map has non-string keys. With
@@ -252,15 +340,32 @@ impl State {
             .expect("state file serialization unexpectedly failed");
         serialized.push(b'\n');
 
-        let mut f = open_file_for_write(&inner.path)
+        write_file_atomically(&inner.path, &serialized)
             .map_err(|e| Error::CantWriteStateFile(inner.path.clone(), e))?;
-        f.write_all(&serialized)
-            .map_err(|e| Error::CantWriteStateFile(inner.path.clone(), WriteFileError::Io(e)))?;
+        log::debug!("saved the state file at {}", inner.path.display());
 
         Ok(())
     }
 }
 
+/// Hex-encoded SHA256 checksums of the binaries needing a binary proxy, as listed in the signed
+/// package manifests. The checksums are not computed from the installed files, as those could have
+/// been modified after they were verified.
+fn binary_proxy_digests(packages: &[VerifiedPackage]) -> BTreeMap<String, String> {
+    packages
+        .iter()
+        .flat_map(|package| package.proxies_sha256.iter())
+        .map(|(name, sha256)| (name.clone(), hex_encode(sha256)))
+        .collect()
+}
+
+/// Hex-encoded SHA256 checksum of the file at `path`.
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex_encode(&hasher.finalize()))
+}
+
 /// Helper for any method or function in State to canonicalize the manifest path.
 fn canonicalize_or_err(manifest_path: &Path) -> Result<PathBuf, Error> {
     let manifest =
@@ -276,9 +381,129 @@ fn canonicalize_or_err(manifest_path: &Path) -> Result<PathBuf, Error> {
 struct StateInner {
     path: PathBuf,
     repr: StateRepr,
+    /// Original state file, if it was written by an older release and migrated when loading it.
+    migrated_from: Option<MigratedFrom>,
+}
+
+struct MigratedFrom {
+    version: u32,
+    contents: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct VersionDetector {
+    version: u32,
+}
+
+/// Parse the contents of the state file at `path`, migrating them if they were written by an
+/// older release.
+fn parse(path: &Path, contents: Vec<u8>) -> Result<(StateRepr, Option<MigratedFrom>), Error> {
+    // The version is deserialized first, as state files of older versions might not
+    // deserialize into the current `StateRepr` until they are migrated.
+    let version = serde_json::from_slice::<VersionDetector>(&contents)
+        .map_err(|e| Error::CorruptStateFile(path.into(), e))?
+        .version;
+    if version > CURRENT_FORMAT_VERSION {
+        return Err(Error::UnsupportedStateFileVersion(path.into(), version));
+    }
+
+    if version < CURRENT_FORMAT_VERSION {
+        log::info!(
+            "migrating the state file at {} from format version {version} to {CURRENT_FORMAT_VERSION}",
+            path.display()
+        );
+        let mut value: serde_json::Value = serde_json::from_slice(&contents)
+            .map_err(|e| Error::CorruptStateFile(path.into(), e))?;
+        migrations::migrate(&mut value, version, CURRENT_FORMAT_VERSION).map_err(
+            |(from, reason)| match reason {
+                Some(reason) => Error::StateFileMigrationFailed {
+                    path: path.into(),
+                    from,
+                    reason,
+                },
+                None => Error::UnsupportedStateFileVersion(path.into(), from),
+            },
+        )?;
+        let repr =
+            serde_json::from_value(value).map_err(|e| Error::CorruptStateFile(path.into(), e))?;
+        Ok((repr, Some(MigratedFrom { version, contents })))
+    } else {
+        let repr = serde_json::from_slice(&contents)
+            .map_err(|e| Error::CorruptStateFile(path.into(), e))?;
+        Ok((repr, None))
+    }
+}
+
+/// Path the last state file that could be parsed is backed up to, `state.json.backup`.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".backup");
+    path.with_file_name(file_name)
+}
+
+/// Path the original state file is backed up to before a state file migrated from `version` is
+/// overwritten, for example `state.json.v1.backup`.
+fn migration_backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".v{version}.backup"));
+    path.with_file_name(file_name)
 }
 
 impl StateInner {
+    /// Load the content of the state file at `path`, migrating it if it was written by an older
+    /// release.
+    fn read(path: PathBuf) -> Result<Self, Error> {
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("no state file at {}, starting empty", path.display());
+                return Ok(StateInner {
+                    path,
+                    repr: StateRepr::default(),
+                    migrated_from: None,
+                });
+            }
+            Err(err) => return Err(Error::CantReadStateFile(path, err)),
+        };
+
+        match parse(&path, contents) {
+            Ok((repr, migrated_from)) => {
+                log::debug!(
+                    "loaded the state file at {} with {} installations",
+                    path.display(),
+                    repr.installations.len()
+                );
+                Ok(StateInner {
+                    path,
+                    repr,
+                    migrated_from,
+                })
+            }
+            // State files are written atomically, but they could still be damaged by disk
+            // failures or by older releases of criticalup crashing while writing them.
+            Err(err @ Error::CorruptStateFile(..)) => {
+                let backup = backup_path(&path);
+                let recovered = std::fs::read(&backup)
+                    .ok()
+                    .and_then(|contents| parse(&path, contents).ok());
+                let Some((repr, migrated_from)) = recovered else {
+                    return Err(err);
+                };
+                log::warn!(
+                    "the state file at {} is corrupt, recovering it from the backup at {}",
+                    path.display(),
+                    backup.display()
+                );
+                Ok(StateInner {
+                    path,
+                    repr,
+                    migrated_from,
+                })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Removes a manifest path from all installations and returns the list of `InstallationId`s
     /// that had the said manifest.
     fn remove_manifest_from_all_installations(
@@ -321,7 +546,6 @@ impl StateInner {
                 let _ = installation.manifests.insert(manifest.to_path_buf());
                 Ok(())
             }
-            // Maybe this arm can use some tracing.
             None => Err(InstallationDoesNotExist(installation_id.0.to_owned())),
         }
     }
@@ -352,13 +576,103 @@ pub struct StateInstallation {
     binary_proxies: BTreeMap<String, PathBuf>,
     #[serde(default)]
     manifests: BTreeSet<PathBuf>,
+    /// Packages in the installation, as recorded before state format version 2. Newer
+    /// installations list them in their metadata instead.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    packages: BTreeSet<String>,
+    /// Hex-encoded SHA256 checksums of the binaries binary proxies resolve to, taken from the
+    /// signed package manifests when the installation is created so that binary proxies can check
+    /// them before every execution without verifying the whole installation.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    binary_proxy_digests: BTreeMap<String, String>,
+    /// Installations created before state format version 2 don't have metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<InstallationMetadata>,
 }
 
 impl StateInstallation {
+    fn new(packages: &[VerifiedPackage], manifests: BTreeSet<PathBuf>) -> Self {
+        StateInstallation {
+            binary_proxies: packages
+                .iter()
+                .flat_map(|package| package.proxies_paths.iter())
+                .map(|(k, v)| (k.clone(), v.into()))
+                .collect(),
+            manifests,
+            packages: BTreeSet::new(),
+            binary_proxy_digests: binary_proxy_digests(packages),
+            metadata: None,
+        }
+    }
+
     /// Get all manifests for a given `StateInstallation`.
     pub fn manifests(&self) -> &BTreeSet<PathBuf> {
         &self.manifests
     }
+
+    /// Get all packages installed for a given `StateInstallation`, including dependencies.
+    /// Installations created before dependencies were resolved don't record them.
+    pub fn packages(&self) -> BTreeSet<&str> {
+        match &self.metadata {
+            Some(metadata) => metadata
+                .packages
+                .iter()
+                .map(|package| package.package.as_str())
+                .collect(),
+            None => self.packages.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Get the binary proxies of the installation, with the paths of the binaries they resolve to
+    /// relative to the installation directory.
+    pub fn binary_proxies(&self) -> &BTreeMap<String, PathBuf> {
+        &self.binary_proxies
+    }
+
+    /// Get what the installation contains, if it was recorded when it was installed.
+    pub fn metadata(&self) -> Option<&InstallationMetadata> {
+        self.metadata.as_ref()
+    }
+}
+
+/// Details about an installation, recorded when it's installed so that it can be described
+/// without deriving them from its `InstallationId`.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct InstallationMetadata {
+    pub product: String,
+    pub release: String,
+    /// Commit the release was built from.
+    pub commit: String,
+    /// Packages in the installation, including dependencies.
+    pub packages: Vec<InstallationPackage>,
+    /// When the installation was created, in seconds since the Unix epoch.
+    pub installed_at: u64,
+    /// Version of criticalup that created the installation.
+    pub criticalup_version: String,
+    /// Total size of the files in the installation, in bytes.
+    pub size: u64,
+}
+
+/// Package in an installation, with the archive it was installed from.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub struct InstallationPackage {
+    pub package: String,
+    /// Format of the archive, for example `tar.xz`.
+    pub format: String,
+    /// Hex-encoded SHA256 checksum of the archive.
+    pub sha256: String,
+}
+
+impl InstallationPackage {
+    pub fn new(package: &str, artifact: &ReleaseArtifact) -> Self {
+        InstallationPackage {
+            package: package.into(),
+            format: artifact.format.to_string(),
+            sha256: hex_encode(&artifact.sha256),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -406,7 +720,8 @@ impl std::fmt::Debug for AuthenticationToken {
 
 #[cfg(test)]
 mod tests {
-    use crate::test_utils::TestEnvironment;
+    use crate::errors::WriteFileError;
+    use crate::test_utils::{TestEnvironment, SAMPLE_AUTH_TOKEN};
 
     use super::*;
 
@@ -450,6 +765,78 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_load_state_with_older_version() {
+        let test_env = TestEnvironment::prepare();
+        let state_file = &test_env.config().paths.state_file;
+        let backup = state_file.with_file_name("state.json.v1.backup");
+
+        let original = serde_json::json!({
+            "version": 1,
+            "authentication_token": "hello",
+            "installations": {
+                "sample": {
+                    "binary_proxies": { "rustc": "bin/rustc" },
+                    "manifests": ["/path/to/criticalup.toml"],
+                    "packages": ["rustc"],
+                },
+            },
+        })
+        .to_string();
+        std::fs::write(state_file, &original).unwrap();
+
+        let state = State::load(test_env.config()).unwrap();
+        assert_eq!(CURRENT_FORMAT_VERSION, state.inner.borrow().repr.version);
+        {
+            let installations = state.installations();
+            let installation = &installations[&InstallationId("sample".into())];
+            assert!(installation.metadata().is_none());
+            assert_eq!(BTreeSet::from(["rustc"]), installation.packages());
+        }
+        assert_eq!(
+            Some(AuthenticationToken("hello".into())),
+            state.authentication_token(None)
+        );
+
+        // The original state file is only backed up once it's overwritten.
+        assert!(!backup.exists());
+        state.persist().unwrap();
+        assert_eq!(original, std::fs::read_to_string(&backup).unwrap());
+
+        let migrated: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(state_file).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::json!(CURRENT_FORMAT_VERSION),
+            migrated["version"]
+        );
+        assert!(State::load(test_env.config())
+            .unwrap()
+            .inner
+            .borrow()
+            .migrated_from
+            .is_none());
+    }
+
+    #[test]
+    fn test_load_state_without_migration() {
+        let test_env = TestEnvironment::prepare();
+
+        std::fs::write(
+            &test_env.config().paths.state_file,
+            serde_json::json!({ "version": 0 }).to_string(),
+        )
+        .unwrap();
+
+        match State::load(test_env.config()) {
+            Err(Error::UnsupportedStateFileVersion(path, version)) => {
+                assert_eq!(test_env.config().paths.state_file, path);
+                assert_eq!(0, version);
+            }
+            Err(err) => panic!("unexpected error when loading the state: {err:?}"),
+            Ok(_) => panic!("loading the state file succeeded"),
+        }
+    }
+
     #[test]
     fn save_same_manifest_content_new_proj_if_existing_installation() {
         let test_env = TestEnvironment::with().state().prepare();
@@ -466,6 +853,7 @@ mod tests {
             product: "ferrocene".to_string(),
             package: "rusty".to_string(),
             proxies_paths: binary_proxies,
+            proxies_sha256: BTreeMap::new(),
         };
 
         // Add installation and write the state file.
@@ -521,6 +909,7 @@ mod tests {
             product: "ferrocene".to_string(),
             package: "rusty".to_string(),
             proxies_paths: binary_proxies,
+            proxies_sha256: BTreeMap::new(),
         };
 
         let proj1 = root.join("path/to/proj/1");
@@ -594,6 +983,7 @@ mod tests {
             product: "ferrocene".to_string(),
             package: "rusty".to_string(),
             proxies_paths: binary_proxies_1,
+            proxies_sha256: BTreeMap::new(),
         };
 
         // Add installation 1 and write the state file.
@@ -617,6 +1007,7 @@ mod tests {
             product: "ferrocene".to_string(),
             package: "rusty".to_string(),
             proxies_paths: binary_proxies_2,
+            proxies_sha256: BTreeMap::new(),
         };
 
         // Add installation 2 and write the state file.
@@ -777,6 +1168,62 @@ mod tests {
         assert_eq!(Some(token), new_state.authentication_token(None));
     }
 
+    #[test]
+    fn test_persist_state_keeps_backup() {
+        let test_env = TestEnvironment::with().state().prepare();
+        let state_file = &test_env.config().paths.state_file;
+        let backup = state_file.with_file_name("state.json.backup");
+        let state = test_env.state();
+        state.persist().unwrap();
+        assert!(!backup.exists());
+
+        let previous = std::fs::read(state_file).unwrap();
+        state.set_authentication_token(Some(AuthenticationToken("new".into())));
+        state.persist().unwrap();
+        assert_eq!(previous, std::fs::read(&backup).unwrap());
+
+        // Only the state file and its backup are left, without temporary files.
+        let mut files = std::fs::read_dir(test_env.root())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.contains("state"))
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(vec!["state.json", "state.json.backup"], files);
+
+        // Corrupt state files are not backed up.
+        std::fs::write(state_file, b"").unwrap();
+        state.persist().unwrap();
+        assert_eq!(previous, std::fs::read(&backup).unwrap());
+    }
+
+    #[test]
+    fn test_load_corrupt_state_recovers_from_backup() {
+        let test_env = TestEnvironment::with().state().prepare();
+        let state_file = &test_env.config().paths.state_file;
+        let state = test_env.state();
+        state.persist().unwrap();
+
+        state.set_authentication_token(Some(AuthenticationToken("new".into())));
+        state.persist().unwrap();
+        // Simulate a write interrupted by an older release of criticalup.
+        std::fs::write(state_file, br#"{"version": 2, "authentication_tok"#).unwrap();
+
+        let recovered = State::load(test_env.config()).unwrap();
+        assert_eq!(
+            Some(AuthenticationToken(SAMPLE_AUTH_TOKEN.into())),
+            recovered.authentication_token(None)
+        );
+
+        // Without a usable backup the state file can't be loaded.
+        std::fs::write(state_file.with_file_name("state.json.backup"), b"").unwrap();
+        match State::load(test_env.config()) {
+            Err(Error::CorruptStateFile(path, _)) => assert_eq!(state_file, &path),
+            Err(err) => panic!("unexpected error when loading the state: {err:?}"),
+            Ok(_) => panic!("loading the state file succeeded"),
+        }
+    }
+
     #[test]
     fn test_persist_state_with_fs_io_error() {
         let test_env = TestEnvironment::with().state().prepare();
@@ -843,11 +1290,13 @@ mod tests {
                         product: "ferrocene".into(),
                         package: "foo".into(),
                         proxies_paths: btreemap! { "a" => "foo/a" },
+                        proxies_sha256: BTreeMap::new(),
                     },
                     VerifiedPackage {
                         product: "ferrocene".into(),
                         package: "bar".into(),
                         proxies_paths: btreemap! { "b" => "foo/b" },
+                        proxies_sha256: BTreeMap::new(),
                     },
                 ],
                 &inst1_manifest_path,
@@ -868,6 +1317,7 @@ mod tests {
                     product: "ferrocene".into(),
                     package: "foo".into(),
                     proxies_paths: btreemap! { "a" => "bar/a" },
+                    proxies_sha256: BTreeMap::new(),
                 }],
                 &inst2_manifest_path,
                 test_env.config(),
@@ -889,6 +1339,153 @@ mod tests {
         assert!(state.resolve_binary_proxy(&id1, "b").is_none());
     }
 
+    #[test]
+    fn test_binary_proxy_checksums() {
+        let test_env = TestEnvironment::with().state().prepare();
+        let state = test_env.state();
+
+        let id = InstallationId("sample".into());
+        let manifest_path = test_env.root().join("proj/manifest");
+        std::fs::create_dir_all(&manifest_path).unwrap();
+        let binary = test_env
+            .config()
+            .paths
+            .installation_dir
+            .join("sample/bin/a");
+        std::fs::create_dir_all(binary.parent().unwrap()).unwrap();
+        std::fs::write(&binary, b"modified").unwrap();
+
+        // The checksums come from the package manifests, not from the files on disk.
+        state
+            .add_installation(
+                &id,
+                &[VerifiedPackage {
+                    product: "ferrocene".into(),
+                    package: "foo".into(),
+                    proxies_paths: btreemap! { "a" => "bin/a", "b" => "bin/b" },
+                    proxies_sha256: btreemap! { "a" => Sha256::digest(b"hello").to_vec() },
+                }],
+                &manifest_path,
+                test_env.config(),
+            )
+            .unwrap();
+        assert_eq!(
+            Some(false),
+            state.binary_proxy_matches(&id, "a", &binary).unwrap()
+        );
+        // Binaries without a checksum in the package manifests have no checksum.
+        assert_eq!(None, state.binary_proxy_matches(&id, "b", &binary).unwrap());
+
+        std::fs::write(&binary, b"hello").unwrap();
+        assert_eq!(
+            Some(true),
+            state.binary_proxy_matches(&id, "a", &binary).unwrap()
+        );
+
+        // Adding the installation again records the new checksums.
+        state
+            .add_installation(
+                &id,
+                &[VerifiedPackage {
+                    product: "ferrocene".into(),
+                    package: "foo".into(),
+                    proxies_paths: btreemap! { "a" => "bin/a" },
+                    proxies_sha256: btreemap! { "a" => Sha256::digest(b"world").to_vec() },
+                }],
+                &manifest_path,
+                test_env.config(),
+            )
+            .unwrap();
+        assert_eq!(
+            Some(false),
+            state.binary_proxy_matches(&id, "a", &binary).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_installation_metadata() {
+        let test_env = TestEnvironment::with().state().prepare();
+        let state = test_env.state();
+
+        let id = InstallationId("sample".into());
+        let manifest_path = test_env.root().join("proj/manifest");
+        std::fs::create_dir_all(&manifest_path).unwrap();
+        let metadata = InstallationMetadata {
+            product: "ferrocene".into(),
+            release: "stable-1.0.0".into(),
+            commit: "abcdef".into(),
+            packages: vec![InstallationPackage::new(
+                "rustc",
+                &ReleaseArtifact {
+                    format: criticaltrust::manifests::ReleaseArtifactFormat::TarXz,
+                    size: 10,
+                    sha256: vec![0x12, 0xab],
+                },
+            )],
+            installed_at: 1700000000,
+            criticalup_version: "1.0.0".into(),
+            size: 1234,
+        };
+
+        assert!(matches!(
+            state.set_installation_metadata(&id, metadata.clone()),
+            Err(InstallationDoesNotExist(_))
+        ));
+
+        state
+            .add_installation(&id, &[], &manifest_path, test_env.config())
+            .unwrap();
+        state
+            .set_installation_metadata(&id, metadata.clone())
+            .unwrap();
+        state.persist().unwrap();
+
+        let state = State::load(test_env.config()).unwrap();
+        let installations = state.installations();
+        let recorded = installations[&id].metadata().unwrap();
+        assert_eq!(&metadata, recorded);
+        assert_eq!("tar.xz", recorded.packages[0].format);
+        assert_eq!("12ab", recorded.packages[0].sha256);
+        assert_eq!(BTreeSet::from(["rustc"]), installations[&id].packages());
+    }
+
+    #[test]
+    fn test_update_includes_changes_of_other_processes() {
+        let test_env = TestEnvironment::with().state().prepare();
+        let manifest_path = test_env.root().join("proj/manifest");
+        std::fs::create_dir_all(&manifest_path).unwrap();
+
+        // Simulate two processes loading the state at the same time.
+        let first = State::load(test_env.config()).unwrap();
+        let second = State::load(test_env.config()).unwrap();
+
+        first
+            .update(test_env.config(), |state| {
+                state.add_installation(
+                    &InstallationId("sample".into()),
+                    &[],
+                    &manifest_path,
+                    test_env.config(),
+                )
+            })
+            .unwrap();
+        second
+            .update(test_env.config(), |state| {
+                state.set_authentication_token(Some(AuthenticationToken::seal("new")));
+                Ok::<_, Error>(())
+            })
+            .unwrap();
+
+        let state = State::load(test_env.config()).unwrap();
+        assert!(state
+            .installations()
+            .contains_key(&InstallationId("sample".into())));
+        assert_eq!(
+            Some(AuthenticationToken::seal("new")),
+            state.authentication_token(None)
+        );
+    }
+
     #[test]
     fn test_default_state_values() {
         // This test ensures the default values for the state file do not change ACCIDENTALLY. If
@@ -896,7 +1493,7 @@ mod tests {
         // to reflect the new defaults.
         assert_eq!(
             StateRepr {
-                version: 1,
+                version: 2,
                 authentication_token: None,
                 installations: BTreeMap::new(),
             },
@@ -934,6 +1531,7 @@ mod tests {
             product: "ferrocene".to_string(),
             package: "rusty".to_string(),
             proxies_paths: BTreeMap::default(),
+            proxies_sha256: BTreeMap::new(),
         };
 
         let proj1 = root.join("path/to/proj/1");
@@ -942,7 +1540,7 @@ mod tests {
         state
             .add_installation(
                 &installation_id_1,
-                &[verified_package.clone()],
+                std::slice::from_ref(&verified_package),
                 &proj1,
                 test_env.config(),
             )
@@ -956,7 +1554,7 @@ mod tests {
         state
             .add_installation(
                 &installation_id_2,
-                &[verified_package.clone()],
+                std::slice::from_ref(&verified_package),
                 &proj2,
                 test_env.config(),
             )
@@ -6,7 +6,7 @@ use crate::download_server_client::DownloadServerClient;
 use crate::state::{AuthenticationToken, State};
 use criticaltrust::keys::{EphemeralKeyPair, KeyAlgorithm, KeyPair, KeyRole, PublicKey};
 use criticaltrust::signatures::SignedPayload;
-use mock_download_server::MockServer;
+use mock_download_server::{Data, MockServer};
 use std::path::Path;
 use tempfile::TempDir;
 
@@ -32,6 +32,8 @@ impl TestEnvironment {
             download_server: false,
             keys: false,
             root_in_subdir: None,
+            retries: 0,
+            tls: false,
         }
     }
 
@@ -67,6 +69,13 @@ impl TestEnvironment {
             .expect("download server not prepared")
             .served_requests_count()
     }
+
+    pub(crate) fn edit_mock_download_server(&self, f: impl FnOnce(&mut Data)) {
+        self.mock_server
+            .as_ref()
+            .expect("download server not prepared")
+            .edit_data(f)
+    }
 }
 
 pub(crate) struct TestEnvironmentBuilder {
@@ -74,6 +83,8 @@ pub(crate) struct TestEnvironmentBuilder {
     download_server: bool,
     keys: bool,
     root_in_subdir: Option<String>,
+    retries: u32,
+    tls: bool,
 }
 
 impl TestEnvironmentBuilder {
@@ -92,6 +103,18 @@ impl TestEnvironmentBuilder {
         self.state().keys()
     }
 
+    /// Serve the mock download server over HTTPS, trusting its certificate authority through
+    /// the `ca-certificates` setting.
+    pub(crate) fn tls(mut self) -> Self {
+        self.tls = true;
+        self.download_server()
+    }
+
+    pub(crate) fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
     pub(crate) fn root_in_subdir(mut self, subdir: &str) -> Self {
         self.root_in_subdir = Some(subdir.into());
         self
@@ -116,6 +139,8 @@ impl TestEnvironmentBuilder {
         }
 
         let mut config = Config::test(root_path).expect("failed to create config");
+        // Tests expecting requests to be retried opt into it explicitly.
+        config.retries = self.retries;
 
         let keys = if self.keys {
             let keys = TestKeys::generate();
@@ -126,8 +151,13 @@ impl TestEnvironmentBuilder {
         };
 
         let mock_server = if self.download_server {
-            let server = start_mock_server(keys.as_ref().unwrap().signed_public_keys());
+            let server = start_mock_server(keys.as_ref().unwrap().signed_public_keys(), self.tls);
             config.whitelabel.download_server_url = server.url();
+            if self.tls {
+                let path = root.path().join("ca.pem");
+                std::fs::write(&path, mock_download_server::TLS_CA_CERTIFICATE).unwrap();
+                config.ca_certificates = Some(path);
+            }
             Some(server)
         } else {
             None
@@ -142,7 +172,10 @@ impl TestEnvironmentBuilder {
         };
 
         let download_server = if self.download_server {
-            Some(DownloadServerClient::new(&config, state.as_ref().unwrap()))
+            Some(
+                DownloadServerClient::new(&config, state.as_ref().unwrap())
+                    .expect("failed to create the download server client"),
+            )
         } else {
             None
         };
@@ -190,7 +223,7 @@ impl TestKeys {
         }
     }
 
-    fn signed_public_keys(&self) -> Vec<SignedPayload<PublicKey>> {
+    pub(crate) fn signed_public_keys(&self) -> Vec<SignedPayload<PublicKey>> {
         let mut result = Vec::new();
         let mut sign = |key: &EphemeralKeyPair, keys: &[&EphemeralKeyPair]| {
             let mut payload = SignedPayload::new(key.public()).unwrap();
@@ -212,7 +245,7 @@ impl TestKeys {
     }
 }
 
-fn start_mock_server(keys: Vec<SignedPayload<PublicKey>>) -> MockServer {
+fn start_mock_server(keys: Vec<SignedPayload<PublicKey>>, tls: bool) -> MockServer {
     use mock_download_server::AuthenticationToken;
 
     let mut builder = mock_download_server::new();
@@ -229,5 +262,9 @@ fn start_mock_server(keys: Vec<SignedPayload<PublicKey>>) -> MockServer {
         builder = builder.add_key(key);
     }
 
-    builder.start()
+    if tls {
+        builder.start_tls()
+    } else {
+        builder.start()
+    }
 }
@@ -3,20 +3,41 @@
 
 use crate::errors::WriteFileError;
 use sha2::{Digest, Sha256};
+use std::ffi::OsString;
 use std::fs::File;
 use std::hash::Hasher;
-use std::io::BufWriter;
+use std::io::Write;
 use std::path::Path;
 
-pub(crate) fn open_file_for_write(path: &Path) -> Result<BufWriter<File>, WriteFileError> {
-    // Ensure the parent directory is always present
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent).map_err(WriteFileError::CantCreateParentDirectory)?;
+/// Write `contents` to `path` so that `path` always has either its previous or its new contents,
+/// even if criticalup crashes or the disk is full while writing.
+///
+/// The contents are written to a temporary file next to `path`, flushed to disk and then renamed
+/// over `path`, as renames within the same directory are atomic.
+pub(crate) fn write_file_atomically(path: &Path, contents: &[u8]) -> Result<(), WriteFileError> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent).map_err(WriteFileError::CantCreateParentDirectory)?;
+
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = parent.join(temp_name);
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
     }
+    result.map_err(WriteFileError::Io)
+}
 
-    Ok(BufWriter::new(
-        File::create(path).map_err(WriteFileError::Io)?,
-    ))
+/// Encode the bytes as a lowercase hexadecimal string.
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
 /// A `Hasher` helper type which is a wrapper to a choice of cryptographic hashing algorithm
@@ -54,6 +75,12 @@ mod tests {
     use super::*;
     use std::hash::Hash;
 
+    #[test]
+    fn test_hex_encode() {
+        assert_eq!("", hex_encode(&[]));
+        assert_eq!("00ff10ab", hex_encode(&[0x00, 0xff, 0x10, 0xab]));
+    }
+
     #[test]
     fn test_sha256_for_a_struct_works() {
         #[derive(Hash)]
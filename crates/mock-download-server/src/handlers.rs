@@ -2,11 +2,12 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use crate::Serialize;
-use crate::{AuthenticationToken, Data};
+use crate::{AuthenticationToken, Data, TransientError};
 use criticaltrust::manifests::ManifestVersion;
+use std::io::Write;
 use tiny_http::{Header, Method, Request, Response, ResponseBox, StatusCode};
 
-pub(crate) fn handle_request(data: &Data, req: &Request) -> ResponseBox {
+pub(crate) fn handle_request(data: &Data, req: &Request) -> Resp {
     let url_parts = req
         .url()
         .split('/')
@@ -19,14 +20,17 @@ pub(crate) fn handle_request(data: &Data, req: &Request) -> ResponseBox {
         (Method::Get, ["v1", "releases", product, release]) => {
             handle_v1_release(data, product, release)
         }
+        (Method::Get, ["v1", "releases", product, release, "download", package, format]) => {
+            handle_v1_package_download(data, req, product, release, package, format)
+        }
         _ => handle_404(),
     };
 
     // Handlers use `Result<Resp, Resp>` to be able to use `?` to propagate error responses. There
     // is no other difference between returning `Ok` or `Err`.
     match resp {
-        Ok(resp) => resp.into_tiny_http(),
-        Err(resp) => resp.into_tiny_http(),
+        Ok(resp) => resp,
+        Err(resp) => resp,
     }
 }
 
@@ -50,20 +54,66 @@ fn handle_v1_release(data: &Data, product: &str, release: &str) -> Result<Resp,
     Ok(resp)
 }
 
+fn handle_v1_package_download(
+    data: &Data,
+    req: &Request,
+    product: &str,
+    release: &str,
+    package: &str,
+    format: &str,
+) -> Result<Resp, Resp> {
+    authorize(data, req)?;
+
+    let archive = data
+        .package_archives
+        .get(&(
+            product.to_string(),
+            release.to_string(),
+            package.to_string(),
+            format.to_string(),
+        ))
+        .ok_or(Resp::NotFound)?;
+
+    let start = match header(req, "range") {
+        Some(range) => parse_range(range, archive.len()).ok_or(Resp::RangeNotSatisfiable)?,
+        None => 0,
+    };
+    let body = Body {
+        data: archive[start..].to_vec(),
+        range: header(req, "range").map(|_| (start, archive.len())),
+    };
+
+    match data.interrupt_package_downloads_after {
+        Some(limit) if limit < body.data.len() => Ok(Resp::Interrupted(body, limit)),
+        _ => Ok(Resp::Binary(body)),
+    }
+}
+
+/// Parse the value of a `Range` header, returning the first byte to send. Only the open-ended
+/// `bytes=N-` form, which is the one used to resume downloads, is supported.
+fn parse_range(range: &str, len: usize) -> Option<usize> {
+    let start: usize = range
+        .strip_prefix("bytes=")?
+        .strip_suffix('-')?
+        .parse()
+        .ok()?;
+    (start < len).then_some(start)
+}
+
 fn handle_404() -> Result<Resp, Resp> {
     Ok(Resp::NotFound)
 }
 
-fn authorize<'a>(data: &'a Data, req: &Request) -> Result<&'a AuthenticationToken, Resp> {
-    let header = req
-        .headers()
+fn header<'a>(req: &'a Request, name: &'static str) -> Option<&'a str> {
+    req.headers()
         .iter()
-        .find(|h| h.field.equiv("authorization"))
-        .ok_or(Resp::Forbidden)?;
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
 
-    let without_prefix = header
-        .value
-        .as_str()
+fn authorize<'a>(data: &'a Data, req: &Request) -> Result<&'a AuthenticationToken, Resp> {
+    let without_prefix = header(req, "authorization")
+        .ok_or(Resp::Forbidden)?
         .strip_prefix("Bearer ")
         .ok_or(Resp::Forbidden)?;
 
@@ -75,10 +125,44 @@ fn authorize<'a>(data: &'a Data, req: &Request) -> Result<&'a AuthenticationToke
 }
 
 #[derive(Debug)]
-enum Resp {
+pub(crate) enum Resp {
     Forbidden,
     NotFound,
+    RangeNotSatisfiable,
     Json(Vec<u8>),
+    Binary(Body),
+    /// Binary response failing after sending the given amount of bytes.
+    Interrupted(Body, usize),
+    Transient(TransientError),
+}
+
+#[derive(Debug)]
+pub(crate) struct Body {
+    data: Vec<u8>,
+    /// First byte and total length of the requested range, if any.
+    range: Option<(usize, usize)>,
+}
+
+impl Body {
+    fn status(&self) -> u16 {
+        if self.range.is_some() {
+            206
+        } else {
+            200
+        }
+    }
+
+    fn headers(&self) -> Vec<Header> {
+        let mut headers =
+            vec![
+                Header::from_bytes(&b"Content-Type"[..], &b"application/octet-stream"[..]).unwrap(),
+            ];
+        if let Some((start, total)) = self.range {
+            let value = format!("bytes {start}-{}/{total}", total - 1);
+            headers.push(Header::from_bytes(&b"Content-Range"[..], value.as_bytes()).unwrap());
+        }
+        headers
+    }
 }
 
 impl Resp {
@@ -87,6 +171,34 @@ impl Resp {
         Resp::Json(serialized)
     }
 
+    pub(crate) fn send(self, request: Request) {
+        match self {
+            Resp::Interrupted(body, limit) => {
+                // tiny_http doesn't allow closing a connection, so the response is sent by hand
+                // with a broken chunked encoding after `limit` bytes, making clients fail in the
+                // same way as if the connection was dropped.
+                let mut writer = request.into_writer();
+                let status = StatusCode(body.status());
+                let mut head = format!(
+                    "HTTP/1.1 {} {}\r\nTransfer-Encoding: chunked\r\n",
+                    status.0,
+                    status.default_reason_phrase()
+                );
+                for header in body.headers() {
+                    head.push_str(&format!("{}: {}\r\n", header.field, header.value));
+                }
+                let _ = write!(writer, "{head}\r\n{limit:x}\r\n");
+                let _ = writer.write_all(&body.data[..limit]);
+                let _ = writer.write_all(b"\r\nnot a chunk\r\n");
+                let _ = writer.flush();
+            }
+            other => {
+                // The client might have closed the connection already.
+                let _ = request.respond(other.into_tiny_http());
+            }
+        }
+    }
+
     fn into_tiny_http(self) -> ResponseBox {
         match self {
             Resp::Json(data) => Response::from_data(data)
@@ -96,8 +208,29 @@ impl Resp {
                 )
                 .boxed(),
 
+            Resp::Binary(body) => {
+                let (status, headers) = (body.status(), body.headers());
+                let mut response = Response::from_data(body.data).with_status_code(status);
+                for header in headers {
+                    response.add_header(header);
+                }
+                response.boxed()
+            }
+
             Resp::Forbidden => Response::empty(StatusCode(403)).boxed(),
             Resp::NotFound => Response::empty(StatusCode(404)).boxed(),
+            Resp::RangeNotSatisfiable => Response::empty(StatusCode(416)).boxed(),
+            Resp::Transient(error) => {
+                let mut response = Response::empty(StatusCode(error.status));
+                if let Some(retry_after) = error.retry_after {
+                    response.add_header(
+                        Header::from_bytes(&b"Retry-After"[..], retry_after.to_string().as_bytes())
+                            .unwrap(),
+                    );
+                }
+                response.boxed()
+            }
+            Resp::Interrupted(..) => unreachable!("interrupted responses are sent manually"),
         }
     }
 }
@@ -2,15 +2,17 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 mod handlers;
+mod proxy;
 mod server;
 
+pub use crate::proxy::MockProxy;
 pub use crate::server::MockServer;
 use criticaltrust::keys::PublicKey;
 use criticaltrust::manifests::ReleaseManifest;
 use criticaltrust::signatures::SignedPayload;
 use serde::Serialize;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "kebab-case")]
@@ -24,14 +26,41 @@ pub struct Data {
     pub tokens: HashMap<String, AuthenticationToken>,
     pub keys: Vec<SignedPayload<PublicKey>>,
     pub release_manifests: HashMap<(String, String), ReleaseManifest>,
+    pub package_archives: HashMap<PackageArchiveKey, Vec<u8>>,
+    /// Break the response after sending this many bytes of a package archive, to simulate network
+    /// failures in the middle of a download.
+    pub interrupt_package_downloads_after: Option<usize>,
+    /// Errors returned by the next requests (one per request), regardless of what was requested,
+    /// to simulate an overloaded or misbehaving server.
+    pub transient_errors: VecDeque<TransientError>,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct TransientError {
+    pub status: u16,
+    /// Value of the `Retry-After` header, in seconds.
+    pub retry_after: Option<u64>,
+}
+
+/// Certificate of the authority signing the certificate of mock servers started with
+/// [`Builder::start_tls`]. The certificates are valid for 100 years, and the one of the server
+/// is valid for `127.0.0.1` and `localhost`.
+pub const TLS_CA_CERTIFICATE: &[u8] = include_bytes!("../certs/ca.pem");
+/// Certificate and private key of a client, signed by [`TLS_CA_CERTIFICATE`].
+pub const TLS_CLIENT_IDENTITY: &[u8] = include_bytes!("../certs/client.pem");
+
+/// Product, release, package and artifact format (for example `tar.xz`) of a package archive.
+pub type PackageArchiveKey = (String, String, String, String);
+
 pub fn new() -> Builder {
     Builder {
         data: Data {
             tokens: HashMap::new(),
             keys: Vec::new(),
             release_manifests: HashMap::new(),
+            package_archives: HashMap::new(),
+            interrupt_package_downloads_after: None,
+            transient_errors: VecDeque::new(),
         },
     }
 }
@@ -63,7 +92,33 @@ impl Builder {
         self
     }
 
+    pub fn add_package_archive(
+        mut self,
+        product: String,
+        release: String,
+        package: String,
+        format: String,
+        archive: Vec<u8>,
+    ) -> Self {
+        self.data
+            .package_archives
+            .insert((product, release, package, format), archive);
+        self
+    }
+
     pub fn start(self) -> MockServer {
-        MockServer::spawn(self.data)
+        MockServer::spawn(self.data, None)
+    }
+
+    /// Start the server accepting only HTTPS connections, with a certificate signed by
+    /// [`TLS_CA_CERTIFICATE`].
+    pub fn start_tls(self) -> MockServer {
+        MockServer::spawn(
+            self.data,
+            Some(tiny_http::SslConfig {
+                certificate: include_bytes!("../certs/server.pem").to_vec(),
+                private_key: include_bytes!("../certs/server-key.pem").to_vec(),
+            }),
+        )
     }
 }
@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: The Ferrocene Developers
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::TLS_CA_CERTIFICATE;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Proxy forwarding requests to a mock server, to test how clients authenticate with proxies
+/// and servers in ways `tiny_http` doesn't support. Only requests without a body are supported,
+/// and each connection is closed after its first response.
+pub struct MockProxy {
+    addr: SocketAddr,
+    kind: Kind,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[derive(Clone)]
+enum Kind {
+    /// HTTP proxy requiring this `Proxy-Authorization` header, like `Basic dXNlcjpzZWNyZXQ=`.
+    Http { authorization: String },
+    /// HTTPS server forwarding requests to the plain HTTP server at `target`.
+    TlsWithClientAuth {
+        target: String,
+        config: Arc<rustls::ServerConfig>,
+    },
+}
+
+impl MockProxy {
+    /// Start an HTTP proxy forwarding requests to the mock server in their URL, responding with
+    /// `407 Proxy Authentication Required` to requests without `proxy_authorization` in their
+    /// `Proxy-Authorization` header.
+    pub fn http(proxy_authorization: &str) -> Self {
+        Self::spawn(Kind::Http {
+            authorization: proxy_authorization.into(),
+        })
+    }
+
+    /// Start an HTTPS server in front of the plain HTTP mock server at `target_url`, with the
+    /// same certificate as [`crate::Builder::start_tls`]. Connections are only accepted from
+    /// clients authenticating with a certificate signed by [`TLS_CA_CERTIFICATE`].
+    pub fn tls_with_client_auth(target_url: &str) -> Self {
+        let target = target_url
+            .strip_prefix("http://")
+            .expect("the target must be a plain HTTP server");
+
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &TLS_CA_CERTIFICATE[..]).unwrap() {
+            roots.add(&rustls::Certificate(cert)).unwrap();
+        }
+        let certs = rustls_pemfile::certs(&mut &include_bytes!("../certs/server.pem")[..])
+            .unwrap()
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        let key =
+            rustls_pemfile::pkcs8_private_keys(&mut &include_bytes!("../certs/server-key.pem")[..])
+                .unwrap()
+                .remove(0);
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(
+                rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed(),
+            )
+            .with_single_cert(certs, rustls::PrivateKey(key))
+            .unwrap();
+
+        Self::spawn(Kind::TlsWithClientAuth {
+            target: target.trim_end_matches('/').into(),
+            config: Arc::new(config),
+        })
+    }
+
+    fn spawn(kind: Kind) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let stop_clone = stop.clone();
+        let kind_clone = kind.clone();
+        let handle = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop_clone.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    // Failures only affect the client of the connection, which sees them.
+                    let _ = handle_connection(&kind_clone, stream);
+                }
+            }
+        });
+
+        Self {
+            addr,
+            kind,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        let scheme = match self.kind {
+            Kind::Http { .. } => "http",
+            Kind::TlsWithClientAuth { .. } => "https",
+        };
+        format!("{scheme}://{}", self.addr)
+    }
+}
+
+impl Drop for MockProxy {
+    fn drop(&mut self) {
+        // Connect to the proxy to wake up the thread waiting for new connections.
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn handle_connection(kind: &Kind, stream: TcpStream) -> std::io::Result<()> {
+    match kind {
+        Kind::Http { authorization } => {
+            let mut stream = stream;
+            let head = read_head(&mut stream)?;
+            let (request_line, headers) = split_head(&head);
+
+            let authorized = headers.iter().any(|header| {
+                header_value(header, "proxy-authorization") == Some(authorization.as_str())
+            });
+            if !authorized {
+                return stream.write_all(
+                    b"HTTP/1.1 407 Proxy Authentication Required\r\n\
+                      Proxy-Authenticate: Basic realm=\"mock\"\r\n\
+                      Content-Length: 0\r\n\
+                      Connection: close\r\n\r\n",
+                );
+            }
+
+            // Requests sent to proxies contain the whole URL, like `GET http://host/path HTTP/1.1`.
+            let mut parts = request_line.splitn(3, ' ');
+            let (method, url, version) = (parts.next(), parts.next(), parts.next());
+            let (target, path) = url
+                .and_then(|url| url.strip_prefix("http://"))
+                .and_then(|url| url.split_once('/'))
+                .ok_or_else(|| std::io::Error::other("unsupported request"))?;
+            let request_line = format!(
+                "{} /{path} {}",
+                method.unwrap_or_default(),
+                version.unwrap_or_default()
+            );
+            let response = forward(target, &request_line, &headers)?;
+            stream.write_all(&response)
+        }
+        Kind::TlsWithClientAuth { target, config } => {
+            let connection =
+                rustls::ServerConnection::new(config.clone()).map_err(std::io::Error::other)?;
+            // The handshake happens while reading the request, and fails without a valid client
+            // certificate.
+            let mut stream = rustls::StreamOwned::new(connection, stream);
+            let head = read_head(&mut stream)?;
+            let (request_line, headers) = split_head(&head);
+
+            let response = forward(target, request_line, &headers)?;
+            stream.write_all(&response)?;
+            stream.conn.send_close_notify();
+            stream.flush()
+        }
+    }
+}
+
+/// Read the request line and the headers of an HTTP request.
+fn read_head(stream: &mut impl Read) -> std::io::Result<String> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+    String::from_utf8(head).map_err(std::io::Error::other)
+}
+
+fn split_head(head: &str) -> (&str, Vec<&str>) {
+    let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+    let request_line = lines.next().unwrap_or_default();
+    (request_line, lines.collect())
+}
+
+fn header_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    let (header_name, value) = header.split_once(':')?;
+    header_name
+        .eq_ignore_ascii_case(name)
+        .then_some(value.trim())
+}
+
+/// Send the request to the server at `target` (like `127.0.0.1:1234`), returning its response.
+fn forward(target: &str, request_line: &str, headers: &[&str]) -> std::io::Result<Vec<u8>> {
+    let mut request = format!("{request_line}\r\n");
+    for header in headers {
+        if header_value(header, "connection").is_none()
+            && header_value(header, "proxy-authorization").is_none()
+        {
+            request.push_str(&format!("{header}\r\n"));
+        }
+    }
+    // The server closes the connection after responding, marking the end of the response.
+    request.push_str("Connection: close\r\n\r\n");
+
+    let mut server = TcpStream::connect(target)?;
+    server.write_all(request.as_bytes())?;
+    let mut response = Vec::new();
+    server.read_to_end(&mut response)?;
+    Ok(response)
+}
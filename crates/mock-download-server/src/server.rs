@@ -1,22 +1,23 @@
 // SPDX-FileCopyrightText: The Ferrocene Developers
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use crate::handlers::handle_request;
+use crate::handlers::{handle_request, Resp};
 use crate::Data;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
-use tiny_http::Server;
+use tiny_http::{Server, SslConfig};
 
 pub struct MockServer {
     data: Arc<Mutex<Data>>,
     server: Arc<Server>,
     handle: Option<JoinHandle<()>>,
     served_requests: Arc<AtomicUsize>,
+    tls: bool,
 }
 
 impl MockServer {
-    pub(crate) fn spawn(data: Data) -> Self {
+    pub(crate) fn spawn(data: Data, tls: Option<SslConfig>) -> Self {
         let data = Arc::new(Mutex::new(data));
 
         // Binding on port 0 results in the operative system picking a random available port,
@@ -24,7 +25,11 @@ impl MockServer {
         // being used by another process.
         //
         // The real port can be then retrieved by checking the address of the bound server.
-        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let tls_enabled = tls.is_some();
+        let server = Arc::new(match tls {
+            Some(config) => Server::https("127.0.0.1:0", config).unwrap(),
+            None => Server::http("127.0.0.1:0").unwrap(),
+        });
 
         let served_requests = Arc::new(AtomicUsize::new(0));
 
@@ -40,11 +45,13 @@ impl MockServer {
             server,
             handle: Some(handle),
             served_requests,
+            tls: tls_enabled,
         }
     }
 
     pub fn url(&self) -> String {
-        format!("http://{}", self.server.server_addr())
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{scheme}://{}", self.server.server_addr())
     }
 
     pub fn served_requests_count(&self) -> usize {
@@ -70,9 +77,17 @@ impl Drop for MockServer {
 
 fn server_thread(data: Arc<Mutex<Data>>, server: Arc<Server>, served_requests: Arc<AtomicUsize>) {
     for request in server.incoming_requests() {
-        let response = handle_request(&data.lock().unwrap(), &request);
-        request.respond(response).unwrap();
+        let response = {
+            let mut data = data.lock().unwrap();
+            match data.transient_errors.pop_front() {
+                Some(error) => Resp::Transient(error),
+                None => handle_request(&data, &request),
+            }
+        };
 
+        // Count the request before responding, as clients might check the count as soon as they
+        // receive the response.
         served_requests.fetch_add(1, Ordering::SeqCst);
+        response.send(request);
     }
 }